@@ -0,0 +1 @@
+DROP TABLE health_checks
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE health_checks (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  slug TEXT NOT NULL,
+  timestamp INTEGER NOT NULL,
+  ok BOOLEAN NOT NULL,
+  failure TEXT,
+  latency_ms INTEGER NOT NULL
+);
+
+CREATE INDEX health_checks_slug_timestamp ON health_checks (slug, timestamp);
+CREATE INDEX health_checks_timestamp ON health_checks (timestamp)
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE webhook_deliveries (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  url TEXT NOT NULL,
+  slug TEXT NOT NULL,
+  old_state TEXT NOT NULL,
+  new_state TEXT NOT NULL,
+  timestamp INTEGER NOT NULL,
+  attempts INTEGER NOT NULL,
+  ok BOOLEAN NOT NULL,
+  status INTEGER,
+  error TEXT
+);
+
+CREATE INDEX webhook_deliveries_timestamp ON webhook_deliveries (timestamp)
\ No newline at end of file
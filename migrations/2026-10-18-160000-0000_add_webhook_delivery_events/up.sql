@@ -0,0 +1 @@
+ALTER TABLE webhook_deliveries ADD COLUMN event TEXT NOT NULL DEFAULT 'health'
\ No newline at end of file
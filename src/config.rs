@@ -1,8 +1,18 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use chrono::Utc;
+use chrono::NaiveDate;
 use rocket::tokio::fs::read_dir;
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+use url::Url;
+
+use crate::{
+    css::{Color, FontStack, InvalidValue, Length},
+    embed::EmbedKind,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ParsedMemberColors {
@@ -12,98 +22,505 @@ pub struct ParsedMemberColors {
     pub on_links: Option<String>,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct MemberColors {
-    pub text: String,
-    pub border: String,
-    pub links: String,
-    pub on_links: String,
+    pub text: Color,
+    pub border: Color,
+    pub links: Color,
+    pub on_links: Color,
+}
+
+/// Parses an optional config value, logging and dropping it if it's invalid so the member keeps
+/// working with the defaults.
+fn parse_or_warn<T: FromStr<Err = InvalidValue>>(
+    slug: &str,
+    field: &str,
+    value: Option<&String>,
+) -> Option<T> {
+    match value?.parse() {
+        Ok(value) => Some(value),
+        Err(err) => {
+            eprintln!("ignoring {} for member {:?}: {}", field, slug, err);
+            None
+        }
+    }
+}
+
+impl Default for MemberColors {
+    fn default() -> Self {
+        Self {
+            text: Color::hex("#000000"),
+            border: Color::hex("#000000"),
+            links: Color::hex("#0000ee"),
+            on_links: Color::hex("#ffffff"),
+        }
+    }
 }
 
 impl MemberColors {
-    pub fn fill_empty_from(from: Option<&ParsedMemberColors>) -> Self {
+    /// Fills in the palette from the `field` config block, using `fallback` for anything that's
+    /// missing or invalid.
+    pub fn fill_empty_from(
+        slug: &str,
+        field: &str,
+        from: Option<&ParsedMemberColors>,
+        fallback: &MemberColors,
+    ) -> Self {
+        let color = |name: &str, value: Option<&Option<String>>, fallback: &Color| {
+            let field = format!("{}.{}", field, name);
+            parse_or_warn(slug, &field, value.and_then(Option::as_ref))
+                .unwrap_or_else(|| fallback.clone())
+        };
+
         Self {
-            text: from
-                .and_then(|f| f.text.clone())
-                .unwrap_or("#000000".to_string()),
-            border: from
-                .and_then(|f| f.border.clone())
-                .unwrap_or("#000000".to_string()),
-            links: from
-                .and_then(|f| f.links.clone())
-                .unwrap_or("#0000ee".to_string()),
-            on_links: from
-                .and_then(|f| f.on_links.clone())
-                .unwrap_or("#ffffff".to_string()),
+            text: color("text", from.map(|f| &f.text), &fallback.text),
+            border: color("border", from.map(|f| &f.border), &fallback.border),
+            links: color("links", from.map(|f| &f.links), &fallback.links),
+            on_links: color("on_links", from.map(|f| &f.on_links), &fallback.on_links),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParsedMemberStatus {
+    Pending,
+    Active,
+    Hiatus,
+    Removed,
+}
+
+/// Where a member is in their lifecycle. Only active members are part of the ring.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum MemberStatus {
+    /// Newly added. Their embed works so they can test it, but nobody links to them yet.
+    Pending,
+    Active,
+    /// Taking a break. They keep their place but aren't checked or linked to until they're back.
+    Hiatus {
+        until: Option<NaiveDate>,
+    },
+    Removed,
+}
+
+impl MemberStatus {
+    /// The status as of `date`, treating a hiatus that has run out as active again.
+    pub fn on(self, date: NaiveDate) -> Self {
+        match self {
+            MemberStatus::Hiatus { until: Some(until) } if until <= date => MemberStatus::Active,
+            status => status,
         }
     }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct ParsedMember {
+    /// Used in embed URLs and stats. Defaults to the file name, so setting it lets the file be
+    /// renamed without breaking anything.
+    pub slug: Option<String>,
+    /// Former slugs that keep working for embeds and healthchecks.
+    pub aliases: Option<Vec<String>>,
+    /// Deprecated in favor of `status: removed`, but still honored.
     pub is_being_removed: Option<bool>,
+    pub status: Option<ParsedMemberStatus>,
+    pub hiatus_until: Option<NaiveDate>,
+    /// When the member joined, used to order the ring when it isn't shuffled.
+    pub joined: Option<NaiveDate>,
     pub name: String,
     pub url: String,
+    pub description: Option<String>,
+    pub pronouns: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub avatar: Option<String>,
+    pub feed: Option<String>,
+    /// The page the healthcheck looks for the embed on, if it isn't `url`.
+    pub healthcheck_url: Option<String>,
+    pub embed_kind: Option<EmbedKind>,
+    /// Where to send a webhook when the member's healthcheck starts or stops passing.
+    pub webhook: Option<String>,
     pub colors: Option<ParsedMemberColors>,
+    pub colors_dark: Option<ParsedMemberColors>,
     pub font_stack: Option<String>,
     pub font_size: Option<String>,
     pub stylesheets: Option<Vec<String>>,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct Member {
-    pub is_being_removed: bool,
+    #[serde(flatten, serialize_with = "serialize_status")]
+    pub status: MemberStatus,
+    pub joined: Option<NaiveDate>,
     pub slug: String,
+    pub aliases: Vec<String>,
     pub name: String,
     pub url: String,
+    pub description: Option<String>,
+    pub pronouns: Option<String>,
+    pub tags: Vec<String>,
+    pub avatar: Option<String>,
+    pub feed: Option<String>,
+    pub healthcheck_url: Option<String>,
+    pub embed_kind: EmbedKind,
+    #[serde(skip)]
+    pub webhook: Option<String>,
     pub colors: MemberColors,
-    pub font_stack: Option<String>,
-    pub font_size: Option<String>,
+    /// Used when the visitor prefers a dark color scheme. Anything left out falls back to `colors`.
+    pub colors_dark: Option<MemberColors>,
+    pub font_stack: Option<FontStack>,
+    pub font_size: Option<Length>,
     pub stylesheets: Vec<String>,
 }
 
-pub async fn read_members() -> anyhow::Result<Vec<Member>> {
-    let mut members = vec![];
+/// Writes `status` along with the `is_being_removed` flag it replaced, which `/members.json`
+/// consumers still filter on.
+fn serialize_status<S: Serializer>(
+    status: &MemberStatus,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry("is_being_removed", &(*status == MemberStatus::Removed))?;
+    map.serialize_entry("status", status)?;
+    map.end()
+}
+
+impl Member {
+    /// The page that has to embed the ring.
+    pub fn healthcheck_url(&self) -> &str {
+        self.healthcheck_url.as_deref().unwrap_or(&self.url)
+    }
+
+    /// Whether `slug` refers to this member, either as their slug or one of their aliases.
+    pub fn answers_to(&self, slug: &str) -> bool {
+        self.slug == slug || self.aliases.iter().any(|alias| alias == slug)
+    }
+}
 
-    let mut files = read_dir("members").await?;
+pub const MAX_DESCRIPTION_LEN: usize = 280;
+pub const MAX_PRONOUNS_LEN: usize = 32;
+pub const MAX_TAGS: usize = 8;
+
+pub fn check_url(value: &str) -> Result<(), String> {
+    match Url::parse(value) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        Ok(url) => Err(format!("unsupported url scheme {:?}", url.scheme())),
+        Err(err) => Err(format!("invalid url {:?}: {}", value, err)),
+    }
+}
+
+pub fn check_description(value: &str) -> Result<(), String> {
+    let len = value.chars().count();
+    if len > MAX_DESCRIPTION_LEN {
+        return Err(format!(
+            "description is {} characters, the limit is {}",
+            len, MAX_DESCRIPTION_LEN
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_pronouns(value: &str) -> Result<(), String> {
+    let len = value.chars().count();
+    if len > MAX_PRONOUNS_LEN {
+        return Err(format!(
+            "pronouns are {} characters, the limit is {}",
+            len, MAX_PRONOUNS_LEN
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_slug(value: &str) -> Result<(), String> {
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "slug {:?} must be alphanumeric with underscores",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Tags are short lowercase words, like `music` or `game-dev`.
+pub fn check_tag(value: &str) -> Result<(), String> {
+    let is_valid = !value.is_empty()
+        && value.len() <= 24
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !is_valid {
+        return Err(format!(
+            "tag {:?} must be up to 24 lowercase letters, digits or dashes",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/// Drops an optional config value that fails `check`, logging why.
+fn check_or_warn(
+    slug: &str,
+    field: &str,
+    value: Option<String>,
+    check: fn(&str) -> Result<(), String>,
+) -> Option<String> {
+    let value = value?;
+    match check(&value) {
+        Ok(()) => Some(value),
+        Err(err) => {
+            eprintln!("ignoring {} for member {:?}: {}", field, slug, err);
+            None
+        }
+    }
+}
+
+impl ParsedMember {
+    /// The member's slug, taken from the file name at `path` unless one is given.
+    pub fn slug(&self, path: &Path) -> String {
+        self.slug.clone().unwrap_or_else(|| slug_from_path(path))
+    }
+
+    pub fn status(&self) -> MemberStatus {
+        if self.is_being_removed == Some(true) {
+            return MemberStatus::Removed;
+        }
+
+        match self.status.unwrap_or(ParsedMemberStatus::Active) {
+            ParsedMemberStatus::Pending => MemberStatus::Pending,
+            ParsedMemberStatus::Active => MemberStatus::Active,
+            ParsedMemberStatus::Hiatus => MemberStatus::Hiatus {
+                until: self.hiatus_until,
+            },
+            ParsedMemberStatus::Removed => MemberStatus::Removed,
+        }
+    }
+}
+
+/// Derives a member's slug from their config file name, without the extension.
+pub fn slug_from_path(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The formats member config files can be written in, picked by file extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemberFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl MemberFormat {
+    /// The format of a member config file, or `None` for anything else in the members directory,
+    /// like a README, an editor swap file or a dotfile.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        if path.file_name()?.to_str()?.starts_with('.') {
+            return None;
+        }
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(MemberFormat::Yaml),
+            "toml" => Some(MemberFormat::Toml),
+            "json" => Some(MemberFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, contents: &[u8]) -> anyhow::Result<ParsedMember> {
+        self.parse_noting_unknown(contents, |_| {})
+    }
+
+    /// Like `parse`, but calls `unknown` with the path of every key that isn't a member setting,
+    /// like `colour` or `colors.txt`, which `parse` silently skips.
+    pub fn parse_noting_unknown(
+        self,
+        contents: &[u8],
+        mut unknown: impl FnMut(String),
+    ) -> anyhow::Result<ParsedMember> {
+        let callback = |path: serde_ignored::Path| unknown(field_name(&path));
+        Ok(match self {
+            MemberFormat::Yaml => {
+                let deserializer = serde_yaml::Deserializer::from_slice(contents);
+                serde_ignored::deserialize(deserializer, callback)?
+            }
+            MemberFormat::Toml => {
+                let mut value: toml::Value = toml::from_str(std::str::from_utf8(contents)?)?;
+                toml_dates_to_strings(&mut value);
+                serde_ignored::deserialize(value, callback)?
+            }
+            MemberFormat::Json => {
+                let mut deserializer = serde_json::Deserializer::from_slice(contents);
+                let member = serde_ignored::deserialize(&mut deserializer, callback)?;
+                deserializer.end()?;
+                member
+            }
+        })
+    }
+}
+
+/// Names the field at `path` the way diagnostics do, like `colors.text` or `tags[0]`.
+fn field_name(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", field_name(parent), index),
+        Path::Map { parent, key } => match field_name(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => field_name(parent),
+    }
+}
+
+/// TOML has its own date type, which chrono can't read. Dates in member files are plain dates, so
+/// they're turned back into the strings the other formats use.
+fn toml_dates_to_strings(value: &mut toml::Value) {
+    match value {
+        toml::Value::Datetime(datetime) => *value = toml::Value::String(datetime.to_string()),
+        toml::Value::Array(values) => values.iter_mut().for_each(toml_dates_to_strings),
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| toml_dates_to_strings(value)),
+        _ => {}
+    }
+}
+
+/// Lists the member config files in `dir`, sorted by path, skipping everything else.
+pub async fn member_files(dir: &Path) -> std::io::Result<Vec<(PathBuf, MemberFormat)>> {
+    let mut paths = vec![];
+    let mut files = read_dir(dir).await?;
     while let Some(file) = files.next_entry().await? {
-        let reader = std::fs::File::open(file.path())?;
-        let member: ParsedMember = match serde_yaml::from_reader(reader) {
+        if file.file_type().await?.is_dir() {
+            continue;
+        }
+        let path = file.path();
+        if let Some(format) = MemberFormat::from_path(&path) {
+            paths.push((path, format));
+        }
+    }
+    paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(paths)
+}
+
+pub async fn read_members(dir: &Path) -> anyhow::Result<Vec<Member>> {
+    let mut members = vec![];
+
+    // Sorted so that when two files claim the same slug, the same one wins every time.
+    let paths = member_files(dir).await?;
+
+    // Every slug and alias in use, and the file that claimed it. Slugs are claimed before any
+    // aliases, so an alias can't take the slug of a member whose file sorts later.
+    let mut claimed: HashMap<String, &Path> = HashMap::new();
+    let mut parsed = vec![];
+    for (path, format) in &paths {
+        let file_name = path.file_name().unwrap_or_default();
+        let contents = std::fs::read(path)?;
+        let member = match format.parse(&contents) {
             Ok(member) => member,
             Err(err) => {
                 eprintln!(
                     "skipping member {:?} due to format error: {}",
-                    file.file_name(),
-                    err
+                    file_name, err
                 );
                 continue;
             }
         };
 
-        let slug = file
-            .file_name()
-            .to_string_lossy()
-            .split('.')
-            .next()
-            .unwrap()
-            .to_string();
+        let slug = member.slug(path);
+        if let Err(err) = check_slug(&slug) {
+            eprintln!("skipping member {:?}: {}", file_name, err);
+            continue;
+        }
+        if let Some(other) = claimed.get(&slug) {
+            eprintln!(
+                "skipping member {:?}: slug {:?} is already used by {:?}",
+                file_name,
+                slug,
+                other.file_name().unwrap_or_default()
+            );
+            continue;
+        }
+        claimed.insert(slug.clone(), path);
+        parsed.push((path, slug, member));
+    }
+
+    for (path, slug, member) in parsed {
+        let mut aliases: Vec<String> = member
+            .aliases
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|alias| check_or_warn(&slug, "aliases", Some(alias), check_slug))
+            .filter(|alias| *alias != slug)
+            .collect();
+        aliases.sort();
+        aliases.dedup();
+        aliases.retain(|alias| match claimed.get(alias.as_str()) {
+            Some(other) => {
+                eprintln!(
+                    "ignoring alias {:?} for member {:?}: it's already used by {:?}",
+                    alias,
+                    slug,
+                    other.file_name().unwrap_or_default()
+                );
+                false
+            }
+            None => true,
+        });
+        for alias in &aliases {
+            claimed.insert(alias.clone(), path);
+        }
+
+        let colors = MemberColors::fill_empty_from(
+            &slug,
+            "colors",
+            member.colors.as_ref(),
+            &MemberColors::default(),
+        );
+        let colors_dark = member
+            .colors_dark
+            .as_ref()
+            .map(|dark| MemberColors::fill_empty_from(&slug, "colors_dark", Some(dark), &colors));
+        let status = member.status();
+        let tags = member
+            .tags
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|tag| check_or_warn(&slug, "tags", Some(tag), check_tag))
+            .take(MAX_TAGS)
+            .collect();
         members.push(Member {
-            is_being_removed: member.is_being_removed.unwrap_or(false),
-            slug,
+            status,
+            joined: member.joined,
             name: member.name,
             url: member.url,
-            colors: MemberColors::fill_empty_from(member.colors.as_ref()),
-            font_stack: member.font_stack,
-            font_size: member.font_size,
+            description: check_or_warn(&slug, "description", member.description, check_description),
+            pronouns: check_or_warn(&slug, "pronouns", member.pronouns, check_pronouns),
+            tags,
+            avatar: check_or_warn(&slug, "avatar", member.avatar, check_url),
+            feed: check_or_warn(&slug, "feed", member.feed, check_url),
+            healthcheck_url: check_or_warn(
+                &slug,
+                "healthcheck_url",
+                member.healthcheck_url,
+                check_url,
+            ),
+            embed_kind: member.embed_kind.unwrap_or_default(),
+            webhook: check_or_warn(&slug, "webhook", member.webhook, check_url),
+            colors,
+            colors_dark,
+            font_stack: parse_or_warn(&slug, "font_stack", member.font_stack.as_ref()),
+            font_size: parse_or_warn(&slug, "font_size", member.font_size.as_ref()),
             stylesheets: member.stylesheets.unwrap_or_default(),
+            slug,
+            aliases,
         });
     }
 
-    members.sort_by_cached_key(|m| {
-        let mut h = DefaultHasher::new();
-        (&m.slug, Utc::now().date_naive()).hash(&mut h);
-        h.finish()
-    });
+    // The ring order depends on the date, so `MemberManager` sorts members as they're read.
+    members.sort_by(|a, b| a.slug.cmp(&b.slug));
 
     Ok(members)
 }
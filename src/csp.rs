@@ -0,0 +1,376 @@
+use scraper::{Html, Selector};
+use url::Url;
+
+use crate::embed::{EmbedForm, FoundEmbed};
+
+/// One `Content-Security-Policy`, reduced to what decides whether a page can load the embed.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// Directive names, lowercased, with their source lists in the order they were written.
+    directives: Vec<(String, Vec<String>)>,
+}
+
+impl Policy {
+    /// Parses a header value, which can hold several comma-separated policies.
+    pub fn parse_header(value: &str) -> Vec<Self> {
+        value.split(',').map(Self::parse).collect()
+    }
+
+    fn parse(policy: &str) -> Self {
+        let mut directives: Vec<(String, Vec<String>)> = vec![];
+        for directive in policy.split(';') {
+            let mut tokens = directive.split_ascii_whitespace();
+            let name = match tokens.next() {
+                Some(name) => name.to_ascii_lowercase(),
+                None => continue,
+            };
+            // Browsers ignore repeated directives.
+            if directives.iter().any(|(existing, _)| *existing == name) {
+                continue;
+            }
+            directives.push((name, tokens.map(str::to_string).collect()));
+        }
+        Self { directives }
+    }
+
+    /// The policies set with `<meta http-equiv>` tags in a page.
+    pub fn from_meta(document: &Html) -> Vec<Self> {
+        let selector = Selector::parse("meta[http-equiv][content]").unwrap();
+        document
+            .select(&selector)
+            .filter(|meta| {
+                meta.attr("http-equiv")
+                    .is_some_and(|name| name.trim().eq_ignore_ascii_case("content-security-policy"))
+            })
+            .filter_map(|meta| meta.attr("content"))
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// The directive that stops `page` from loading `url`, written out like `frame-src 'self'`,
+    /// if this policy does. `fallbacks` lists the directives that govern the load, most specific
+    /// first, like `frame-src`, `child-src`, `default-src`. `nonce` is the loading tag's nonce,
+    /// for scripts.
+    pub fn blocking_directive(
+        &self,
+        fallbacks: &[&str],
+        url: &Url,
+        page: &Url,
+        nonce: Option<&str>,
+    ) -> Option<String> {
+        let (name, sources) = fallbacks
+            .iter()
+            .find_map(|fallback| self.directives.iter().find(|(name, _)| name == fallback))?;
+        let nonce_matches = |source: &str| {
+            nonce.is_some_and(|nonce| {
+                source
+                    .strip_prefix("'nonce-")
+                    .and_then(|rest| rest.strip_suffix('\''))
+                    == Some(nonce)
+            })
+        };
+        if sources
+            .iter()
+            .any(|source| nonce_matches(source) || source_matches(source, url, page))
+        {
+            return None;
+        }
+        Some(
+            std::iter::once(name.as_str())
+                .chain(sources.iter().map(String::as_str))
+                .collect::<Vec<&str>>()
+                .join(" "),
+        )
+    }
+}
+
+/// Whether a CSP source expression allows `url` on `page`. Keywords other than `'self'`, like
+/// `'unsafe-inline'`, never allow loading a frame or an external script.
+fn source_matches(source: &str, url: &Url, page: &Url) -> bool {
+    let source = source.to_ascii_lowercase();
+    // A page served over http can load https, but not the other way around.
+    let scheme_matches =
+        |scheme: &str| scheme == url.scheme() || (scheme == "http" && url.scheme() == "https");
+
+    if source == "*" {
+        return matches!(url.scheme(), "http" | "https");
+    }
+    if source == "'self'" {
+        return scheme_matches(page.scheme())
+            && url.host_str() == page.host_str()
+            && url.port_or_known_default() == page.port_or_known_default();
+    }
+    if source.starts_with('\'') {
+        return false;
+    }
+    if let Some(scheme) = source.strip_suffix(':') {
+        return scheme_matches(scheme);
+    }
+
+    let (scheme, rest) = match source.split_once("://") {
+        Some((scheme, rest)) => (scheme, rest),
+        None => (page.scheme(), source.as_str()),
+    };
+    if !scheme_matches(scheme) {
+        return false;
+    }
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+
+    let url_host = url.host_str().unwrap_or_default();
+    let host_matches = match host.strip_prefix("*.") {
+        Some(parent) => url_host
+            .strip_suffix(parent)
+            .is_some_and(|sub| sub.ends_with('.') && sub.len() > 1),
+        None => host == "*" || host == url_host,
+    };
+    let port_matches = match port {
+        Some("*") => true,
+        Some(port) => port.parse().ok() == url.port_or_known_default(),
+        // Without a port, only the default one is allowed, and the scheme has already been
+        // checked, so this also lets http sources load https on 443.
+        None => url.port().is_none(),
+    };
+    let path_matches = if path.is_empty() || path == "/" {
+        true
+    } else if path.ends_with('/') {
+        url.path().starts_with(path)
+    } else {
+        url.path() == path
+    };
+    host_matches && port_matches && path_matches
+}
+
+/// The directive in any of `policies` that stops `page` from showing `embed`, if there is one.
+/// Links can't be blocked, and script embeds are checked for loading `widget.js` and then for the
+/// iframe it adds.
+pub fn blocking_directive(policies: &[Policy], embed: &FoundEmbed, page: &Url) -> Option<String> {
+    if embed.form == EmbedForm::Script {
+        let fallbacks = ["script-src-elem", "script-src", "default-src"];
+        let blocked = policies.iter().find_map(|policy| {
+            policy.blocking_directive(&fallbacks, &embed.url, page, embed.nonce.as_deref())
+        });
+        if blocked.is_some() {
+            return blocked;
+        }
+    }
+
+    let (fallbacks, url): (&[&str], Url) = match embed.form {
+        EmbedForm::Iframe => (
+            &["frame-src", "child-src", "default-src"],
+            embed.url.clone(),
+        ),
+        EmbedForm::Object => (&["object-src", "default-src"], embed.url.clone()),
+        EmbedForm::Script => {
+            let mut url = embed.url.clone();
+            let path = url.path().trim_end_matches("/widget.js").to_string();
+            url.set_path(&path);
+            (&["frame-src", "child-src", "default-src"], url)
+        }
+        EmbedForm::Link => return None,
+    };
+    policies
+        .iter()
+        .find_map(|policy| policy.blocking_directive(fallbacks, &url, page, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed::Visibility;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn allows(source: &str, target: &str, page: &str) -> bool {
+        source_matches(source, &url(target), &url(page))
+    }
+
+    fn embed(form: EmbedForm, src: &str, nonce: Option<&str>) -> FoundEmbed {
+        FoundEmbed {
+            form,
+            slug: Some("someone".to_string()),
+            url: url(src),
+            nonce: nonce.map(str::to_string),
+            visibility: Visibility::Visible,
+        }
+    }
+
+    const PAGE: &str = "https://member.example/";
+    const IFRAME: &str = "https://ring.example/embed/someone";
+    const SCRIPT: &str = "https://ring.example/embed/someone/widget.js";
+
+    fn blocked(header: &str, embed: &FoundEmbed) -> Option<String> {
+        blocking_directive(&Policy::parse_header(header), embed, &url(PAGE))
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(allows("*", IFRAME, PAGE));
+        assert!(!allows("*", "data:text/html,hi", PAGE));
+        assert!(allows("*.example", IFRAME, PAGE));
+        assert!(allows("https://*.example", IFRAME, PAGE));
+        assert!(!allows("*.ring.example", IFRAME, PAGE));
+        assert!(!allows("*.example", "https://example/", PAGE));
+    }
+
+    #[test]
+    fn matches_schemes() {
+        assert!(allows("https:", IFRAME, PAGE));
+        assert!(allows("http:", IFRAME, PAGE));
+        assert!(!allows("https:", "http://ring.example/", PAGE));
+        assert!(!allows("data:", IFRAME, PAGE));
+        assert!(allows("http://ring.example", IFRAME, PAGE));
+        assert!(!allows(
+            "https://ring.example",
+            "http://ring.example/",
+            PAGE
+        ));
+        // Without a scheme, the page's is assumed.
+        assert!(allows("ring.example", IFRAME, PAGE));
+        assert!(!allows("ring.example", IFRAME, "wss://member.example/"));
+    }
+
+    #[test]
+    fn matches_keywords() {
+        assert!(allows("'self'", "https://member.example/frame", PAGE));
+        assert!(allows("'SELF'", "https://member.example/frame", PAGE));
+        assert!(!allows("'self'", IFRAME, PAGE));
+        assert!(!allows("'self'", "https://member.example:8443/", PAGE));
+        assert!(!allows("'none'", IFRAME, PAGE));
+        assert!(!allows("'unsafe-inline'", IFRAME, PAGE));
+    }
+
+    #[test]
+    fn matches_ports() {
+        assert!(allows("ring.example:443", IFRAME, PAGE));
+        assert!(allows("ring.example:*", "https://ring.example:8443/", PAGE));
+        assert!(!allows("ring.example", "https://ring.example:8443/", PAGE));
+        assert!(!allows("ring.example:8443", IFRAME, PAGE));
+        assert!(allows("http://ring.example", "https://ring.example/", PAGE));
+    }
+
+    #[test]
+    fn matches_paths() {
+        assert!(allows("ring.example/", IFRAME, PAGE));
+        assert!(allows("ring.example/embed/", IFRAME, PAGE));
+        assert!(allows("ring.example/embed/someone", IFRAME, PAGE));
+        assert!(!allows("ring.example/embed", IFRAME, PAGE));
+        assert!(!allows("ring.example/other/", IFRAME, PAGE));
+    }
+
+    #[test]
+    fn falls_back_through_directives() {
+        let iframe = embed(EmbedForm::Iframe, IFRAME, None);
+        assert_eq!(
+            blocked("default-src 'self'", &iframe).as_deref(),
+            Some("default-src 'self'")
+        );
+        assert_eq!(
+            blocked("default-src 'self'; child-src 'none'", &iframe).as_deref(),
+            Some("child-src 'none'")
+        );
+        assert_eq!(
+            blocked("default-src 'none'; frame-src ring.example", &iframe),
+            None
+        );
+        assert_eq!(blocked("script-src 'none'; img-src 'none'", &iframe), None);
+        // Repeated directives after the first are ignored.
+        assert_eq!(
+            blocked("frame-src ring.example; frame-src 'none'", &iframe),
+            None
+        );
+
+        let object = embed(EmbedForm::Object, IFRAME, None);
+        assert_eq!(
+            blocked("frame-src 'none'; object-src 'none'", &object).as_deref(),
+            Some("object-src 'none'")
+        );
+        let link = embed(EmbedForm::Link, "https://ring.example/", None);
+        assert_eq!(blocked("default-src 'none'", &link), None);
+    }
+
+    #[test]
+    fn checks_every_policy() {
+        let iframe = embed(EmbedForm::Iframe, IFRAME, None);
+        assert_eq!(
+            blocked("frame-src *, frame-src 'self'", &iframe).as_deref(),
+            Some("frame-src 'self'")
+        );
+    }
+
+    #[test]
+    fn checks_script_loads_then_frames() {
+        let script = embed(EmbedForm::Script, SCRIPT, None);
+        assert_eq!(
+            blocked("script-src 'self'", &script).as_deref(),
+            Some("script-src 'self'")
+        );
+        assert_eq!(
+            blocked("script-src 'none'; script-src-elem ring.example", &script),
+            None
+        );
+        assert_eq!(
+            blocked("script-src ring.example; frame-src 'self'", &script).as_deref(),
+            Some("frame-src 'self'")
+        );
+        assert_eq!(
+            blocked("default-src 'self'", &script).as_deref(),
+            Some("default-src 'self'")
+        );
+        assert_eq!(blocked("default-src ring.example/embed/", &script), None);
+
+        let with_nonce = embed(EmbedForm::Script, SCRIPT, Some("abc"));
+        assert_eq!(
+            blocked(
+                "script-src 'nonce-abc'; frame-src ring.example",
+                &with_nonce
+            ),
+            None
+        );
+        assert_eq!(
+            blocked("script-src 'nonce-xyz'", &with_nonce).as_deref(),
+            Some("script-src 'nonce-xyz'")
+        );
+        assert_eq!(
+            blocked("script-src 'nonce-abc'", &script).as_deref(),
+            Some("script-src 'nonce-abc'")
+        );
+    }
+
+    #[test]
+    fn reads_meta_policies() {
+        let document = Html::parse_document(
+            "<head>
+                <meta http-equiv=' Content-Security-Policy ' content=\"frame-src 'self'\">
+                <meta http-equiv='refresh' content='5'>
+                <meta name='content-security-policy' content=\"frame-src 'none'\">
+            </head>",
+        );
+        let policies = Policy::from_meta(&document);
+        assert_eq!(policies.len(), 1);
+        let iframe = embed(EmbedForm::Iframe, IFRAME, None);
+        assert_eq!(
+            blocking_directive(&policies, &iframe, &url(PAGE)).as_deref(),
+            Some("frame-src 'self'")
+        );
+
+        // Unlike headers, a meta tag holds a single policy, commas and all.
+        let document = Html::parse_document(
+            "<meta http-equiv='content-security-policy' content=\"frame-src *, frame-src 'self'\">",
+        );
+        let policies = Policy::from_meta(&document);
+        assert_eq!(policies.len(), 1);
+        assert_eq!(
+            Policy::parse_header("frame-src *, frame-src 'self'").len(),
+            2
+        );
+    }
+}
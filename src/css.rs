@@ -0,0 +1,623 @@
+use std::{fmt, str::FromStr};
+
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Serialize, Serializer};
+
+const NAMED_COLORS: &[&str] = &[
+    "aliceblue",
+    "antiquewhite",
+    "aqua",
+    "aquamarine",
+    "azure",
+    "beige",
+    "bisque",
+    "black",
+    "blanchedalmond",
+    "blue",
+    "blueviolet",
+    "brown",
+    "burlywood",
+    "cadetblue",
+    "chartreuse",
+    "chocolate",
+    "coral",
+    "cornflowerblue",
+    "cornsilk",
+    "crimson",
+    "currentcolor",
+    "cyan",
+    "darkblue",
+    "darkcyan",
+    "darkgoldenrod",
+    "darkgray",
+    "darkgreen",
+    "darkgrey",
+    "darkkhaki",
+    "darkmagenta",
+    "darkolivegreen",
+    "darkorange",
+    "darkorchid",
+    "darkred",
+    "darksalmon",
+    "darkseagreen",
+    "darkslateblue",
+    "darkslategray",
+    "darkslategrey",
+    "darkturquoise",
+    "darkviolet",
+    "deeppink",
+    "deepskyblue",
+    "dimgray",
+    "dimgrey",
+    "dodgerblue",
+    "firebrick",
+    "floralwhite",
+    "forestgreen",
+    "fuchsia",
+    "gainsboro",
+    "ghostwhite",
+    "gold",
+    "goldenrod",
+    "gray",
+    "green",
+    "greenyellow",
+    "grey",
+    "honeydew",
+    "hotpink",
+    "indianred",
+    "indigo",
+    "ivory",
+    "khaki",
+    "lavender",
+    "lavenderblush",
+    "lawngreen",
+    "lemonchiffon",
+    "lightblue",
+    "lightcoral",
+    "lightcyan",
+    "lightgoldenrodyellow",
+    "lightgray",
+    "lightgreen",
+    "lightgrey",
+    "lightpink",
+    "lightsalmon",
+    "lightseagreen",
+    "lightskyblue",
+    "lightslategray",
+    "lightslategrey",
+    "lightsteelblue",
+    "lightyellow",
+    "lime",
+    "limegreen",
+    "linen",
+    "magenta",
+    "maroon",
+    "mediumaquamarine",
+    "mediumblue",
+    "mediumorchid",
+    "mediumpurple",
+    "mediumseagreen",
+    "mediumslateblue",
+    "mediumspringgreen",
+    "mediumturquoise",
+    "mediumvioletred",
+    "midnightblue",
+    "mintcream",
+    "mistyrose",
+    "moccasin",
+    "navajowhite",
+    "navy",
+    "oldlace",
+    "olive",
+    "olivedrab",
+    "orange",
+    "orangered",
+    "orchid",
+    "palegoldenrod",
+    "palegreen",
+    "paleturquoise",
+    "palevioletred",
+    "papayawhip",
+    "peachpuff",
+    "peru",
+    "pink",
+    "plum",
+    "powderblue",
+    "purple",
+    "rebeccapurple",
+    "red",
+    "rosybrown",
+    "royalblue",
+    "saddlebrown",
+    "salmon",
+    "sandybrown",
+    "seagreen",
+    "seashell",
+    "sienna",
+    "silver",
+    "skyblue",
+    "slateblue",
+    "slategray",
+    "slategrey",
+    "snow",
+    "springgreen",
+    "steelblue",
+    "tan",
+    "teal",
+    "thistle",
+    "tomato",
+    "transparent",
+    "turquoise",
+    "violet",
+    "wheat",
+    "white",
+    "whitesmoke",
+    "yellow",
+    "yellowgreen",
+];
+
+const LENGTH_UNITS: &[&str] = &[
+    "px", "em", "rem", "ex", "ch", "pt", "pc", "in", "cm", "mm", "q", "vw", "vh", "vmin", "vmax",
+    "%",
+];
+
+const FONT_SIZE_KEYWORDS: &[&str] = &[
+    "initial", "inherit", "unset", "xx-small", "x-small", "small", "medium", "large", "x-large",
+    "xx-large", "smaller", "larger",
+];
+
+/// A value that couldn't be parsed as the CSS type it was meant to be.
+#[derive(Debug, Clone)]
+pub struct InvalidValue {
+    kind: &'static str,
+    value: String,
+}
+
+impl InvalidValue {
+    fn new(kind: &'static str, value: &str) -> Self {
+        Self {
+            kind,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not a valid css {}", self.value, self.kind)
+    }
+}
+
+impl std::error::Error for InvalidValue {}
+
+fn parse_number(value: &str) -> Option<f32> {
+    let digits = value.strip_prefix(['-', '+']).unwrap_or(value);
+    let is_number = !digits.is_empty()
+        && digits.chars().filter(|&c| c == '.').count() <= 1
+        && digits.chars().any(|c| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.');
+    if is_number {
+        value.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// A number or percentage inside a color function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Number(f32),
+    Percent(f32),
+}
+
+impl Channel {
+    fn parse(value: &str) -> Option<Self> {
+        match value.strip_suffix('%') {
+            Some(percent) => parse_number(percent).map(Channel::Percent),
+            None => parse_number(value).map(Channel::Number),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Number(number) => write!(f, "{}", number),
+            Channel::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// A CSS color. Only the forms below are accepted, and they're always written back out in a
+/// canonical form, so a color is safe to interpolate into a stylesheet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Color {
+    /// Three, four, six or eight lowercase hex digits, without the `#`.
+    Hex(String),
+    Rgb {
+        red: Channel,
+        green: Channel,
+        blue: Channel,
+        alpha: Option<Channel>,
+    },
+    Hsl {
+        hue: f32,
+        saturation: f32,
+        lightness: f32,
+        alpha: Option<Channel>,
+    },
+    Named(&'static str),
+}
+
+impl Color {
+    pub fn hex(hex: &str) -> Self {
+        hex.parse().expect("invalid hex color literal")
+    }
+}
+
+impl FromStr for Color {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidValue::new("color", value);
+        let lowercase = value.trim().to_ascii_lowercase();
+
+        if let Some(hex) = lowercase.strip_prefix('#') {
+            if matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Ok(Color::Hex(hex.to_string()));
+            }
+            return Err(invalid());
+        }
+
+        if let Some((function, args)) = lowercase.split_once('(') {
+            let args = args.strip_suffix(')').ok_or_else(invalid)?;
+            let args: Vec<&str> = args
+                .split([',', ' ', '/'])
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            if !matches!(args.len(), 3 | 4) {
+                return Err(invalid());
+            }
+            let alpha = match args.get(3) {
+                Some(alpha) => Some(Channel::parse(alpha).ok_or_else(invalid)?),
+                None => None,
+            };
+
+            return match function {
+                "rgb" | "rgba" => Ok(Color::Rgb {
+                    red: Channel::parse(args[0]).ok_or_else(invalid)?,
+                    green: Channel::parse(args[1]).ok_or_else(invalid)?,
+                    blue: Channel::parse(args[2]).ok_or_else(invalid)?,
+                    alpha,
+                }),
+                "hsl" | "hsla" => Ok(Color::Hsl {
+                    hue: parse_number(args[0].strip_suffix("deg").unwrap_or(args[0]))
+                        .ok_or_else(invalid)?,
+                    saturation: args[1]
+                        .strip_suffix('%')
+                        .and_then(parse_number)
+                        .ok_or_else(invalid)?,
+                    lightness: args[2]
+                        .strip_suffix('%')
+                        .and_then(parse_number)
+                        .ok_or_else(invalid)?,
+                    alpha,
+                }),
+                _ => Err(invalid()),
+            };
+        }
+
+        NAMED_COLORS
+            .iter()
+            .find(|&&name| name == lowercase)
+            .map(|&name| Color::Named(name))
+            .ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Hex(hex) => write!(f, "#{}", hex),
+            Color::Rgb {
+                red,
+                green,
+                blue,
+                alpha: None,
+            } => write!(f, "rgb({}, {}, {})", red, green, blue),
+            Color::Rgb {
+                red,
+                green,
+                blue,
+                alpha: Some(alpha),
+            } => write!(f, "rgba({}, {}, {}, {})", red, green, blue, alpha),
+            Color::Hsl {
+                hue,
+                saturation,
+                lightness,
+                alpha: None,
+            } => write!(f, "hsl({}, {}%, {}%)", hue, saturation, lightness),
+            Color::Hsl {
+                hue,
+                saturation,
+                lightness,
+                alpha: Some(alpha),
+            } => write!(
+                f,
+                "hsla({}, {}%, {}%, {})",
+                hue, saturation, lightness, alpha
+            ),
+            Color::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A CSS length, as used for the embed's `font-size`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Keyword(&'static str),
+    Zero,
+    Dimension(f32, &'static str),
+}
+
+impl FromStr for Length {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lowercase = value.trim().to_ascii_lowercase();
+
+        if let Some(&keyword) = FONT_SIZE_KEYWORDS.iter().find(|&&k| k == lowercase) {
+            return Ok(Length::Keyword(keyword));
+        }
+        if lowercase == "0" {
+            return Ok(Length::Zero);
+        }
+
+        LENGTH_UNITS
+            .iter()
+            .find_map(|&unit| {
+                let number = parse_number(lowercase.strip_suffix(unit)?)?;
+                Some(Length::Dimension(number, unit))
+            })
+            .ok_or_else(|| InvalidValue::new("length", value))
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Length::Keyword(keyword) => write!(f, "{}", keyword),
+            Length::Zero => write!(f, "0"),
+            Length::Dimension(number, unit) => write!(f, "{}{}", number, unit),
+        }
+    }
+}
+
+/// One entry in a `font-family` list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFamily {
+    /// A quoted family name, stored without its quotes.
+    Quoted(String),
+    /// An unquoted family name or generic family like `serif`.
+    Plain(String),
+}
+
+/// A `font-family` list. Quoted names can't contain quotes or anything else that would let them
+/// escape the stylesheet, and unquoted names are limited to identifier characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontStack(Vec<FontFamily>);
+
+impl FromStr for FontStack {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidValue::new("font stack", value);
+
+        let families = value
+            .split(',')
+            .map(|family| {
+                let family = family.trim();
+                let quoted = family
+                    .strip_prefix('"')
+                    .and_then(|f| f.strip_suffix('"'))
+                    .or_else(|| family.strip_prefix('\'').and_then(|f| f.strip_suffix('\'')));
+
+                match quoted {
+                    Some(name)
+                        if !name.trim().is_empty()
+                            && name.chars().all(|c| {
+                                c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | '+')
+                            }) =>
+                    {
+                        Ok(FontFamily::Quoted(name.trim().to_string()))
+                    }
+                    None if !family.is_empty()
+                        && family
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_')) =>
+                    {
+                        Ok(FontFamily::Plain(family.to_string()))
+                    }
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<Vec<FontFamily>, InvalidValue>>()?;
+
+        Ok(FontStack(families))
+    }
+}
+
+impl fmt::Display for FontStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let families: Vec<String> = self
+            .0
+            .iter()
+            .map(|family| match family {
+                FontFamily::Quoted(name) => format!("\"{}\"", name),
+                FontFamily::Plain(name) => name.clone(),
+            })
+            .collect();
+        write!(f, "{}", families.join(", "))
+    }
+}
+
+macro_rules! impl_string_conversions {
+    ($($ty:ty),*) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        #[rocket::async_trait]
+        impl<'v> FromFormField<'v> for $ty {
+            fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+                field
+                    .value
+                    .parse()
+                    .map_err(|err: InvalidValue| form::Error::validation(err.to_string()).into())
+            }
+        }
+    )*};
+}
+
+impl_string_conversions!(Color, Length, FontStack);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Things that would let a value break out of the declaration it's interpolated into.
+    const INJECTIONS: &[&str] = &[
+        ";",
+        "}",
+        "</style>",
+        "url(https://example.com/track.png)",
+        "/* comment */",
+        "\\3b",
+        "\\7d",
+        "expression(alert(1))",
+    ];
+
+    fn round_trip<T: FromStr + fmt::Display>(value: &str) -> String
+    where
+        T::Err: fmt::Debug,
+    {
+        value.parse::<T>().unwrap().to_string()
+    }
+
+    #[test]
+    fn rejects_injected_colors() {
+        for injection in INJECTIONS {
+            for value in [
+                injection.to_string(),
+                format!("red{}", injection),
+                format!("#fff{}", injection),
+                format!("rgb(1, 2, 3{})", injection),
+                format!("rgb(1, 2, 3){}", injection),
+                format!("hsl({}0, 50%, 50%)", injection),
+            ] {
+                assert!(value.parse::<Color>().is_err(), "accepted {:?}", value);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_injected_lengths() {
+        for injection in INJECTIONS {
+            for value in [
+                injection.to_string(),
+                format!("1em{}", injection),
+                format!("{}1em", injection),
+                format!("large{}", injection),
+            ] {
+                assert!(value.parse::<Length>().is_err(), "accepted {:?}", value);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_injected_font_stacks() {
+        for injection in INJECTIONS {
+            for value in [
+                injection.to_string(),
+                format!("serif{}", injection),
+                format!("\"Plex{}\", serif", injection),
+                format!("'Plex{}'", injection),
+            ] {
+                assert!(value.parse::<FontStack>().is_err(), "accepted {:?}", value);
+            }
+        }
+        for value in ["\"Plex\"\"", "\"Plex", "'Plex\"", "\"\"", "serif,", ""] {
+            assert!(value.parse::<FontStack>().is_err(), "accepted {:?}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for value in [
+            "#ff",
+            "#fffff",
+            "#ggg",
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4, 5)",
+            "rgb(1, 2, x)",
+            "hsl(1, 2, 3)",
+            "cmyk(1, 2, 3)",
+            "notacolor",
+        ] {
+            assert!(value.parse::<Color>().is_err(), "accepted {:?}", value);
+        }
+    }
+
+    #[test]
+    fn writes_colors_canonically() {
+        assert_eq!(round_trip::<Color>("#ABCDEF"), "#abcdef");
+        assert_eq!(round_trip::<Color>(" #FFF "), "#fff");
+        assert_eq!(round_trip::<Color>("RGB(255 0 10%)"), "rgb(255, 0, 10%)");
+        assert_eq!(round_trip::<Color>("rgba(1,2,3,0.5)"), "rgba(1, 2, 3, 0.5)");
+        assert_eq!(
+            round_trip::<Color>("rgb(1 2 3 / 50%)"),
+            "rgba(1, 2, 3, 50%)"
+        );
+        assert_eq!(
+            round_trip::<Color>("hsl(120deg, 50%, 25%)"),
+            "hsl(120, 50%, 25%)"
+        );
+        assert_eq!(
+            round_trip::<Color>("hsla(120, 50%, 25%, .5)"),
+            "hsla(120, 50%, 25%, 0.5)"
+        );
+        assert_eq!(round_trip::<Color>("RebeccaPurple"), "rebeccapurple");
+        for value in ["#abcdef", "rgb(1, 2, 3)", "hsla(120, 50%, 25%, 0.5)", "red"] {
+            assert_eq!(
+                round_trip::<Color>(&round_trip::<Color>(value)),
+                round_trip::<Color>(value)
+            );
+        }
+    }
+
+    #[test]
+    fn writes_lengths_canonically() {
+        assert_eq!(round_trip::<Length>("1.20EM"), "1.2em");
+        assert_eq!(round_trip::<Length>("16px"), "16px");
+        assert_eq!(round_trip::<Length>("110%"), "110%");
+        assert_eq!(round_trip::<Length>("0"), "0");
+        assert_eq!(round_trip::<Length>("X-Large"), "x-large");
+        for value in ["1.2", "px", "1.2.3em", "1 em", "1furlong"] {
+            assert!(value.parse::<Length>().is_err(), "accepted {:?}", value);
+        }
+    }
+
+    #[test]
+    fn writes_font_stacks_canonically() {
+        assert_eq!(
+            round_trip::<FontStack>("'IBM Plex Mono',monospace"),
+            "\"IBM Plex Mono\", monospace"
+        );
+        assert_eq!(
+            round_trip::<FontStack>(" \"Font Awesome 6.0\" , sans-serif "),
+            "\"Font Awesome 6.0\", sans-serif"
+        );
+    }
+}
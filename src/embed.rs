@@ -0,0 +1,366 @@
+use std::fmt;
+
+use scraper::{node::Element, Html, Node};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The markup a member page can use to embed the ring.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbedForm {
+    /// `<iframe src>`, the snippet from the README.
+    Iframe,
+    /// `<object data>` or `<embed src>`, which some site generators emit instead of iframes.
+    Object,
+    /// `<script src>` loading the ring's `widget.js`, which adds the iframe when the page loads.
+    Script,
+    /// A plain `<a href>` back to the ring.
+    Link,
+}
+
+impl EmbedForm {
+    /// The form an element embeds the ring with, and the attribute holding the embed URL.
+    fn of(element: &Element) -> Option<(Self, &'static str)> {
+        match element.name() {
+            "iframe" => Some((EmbedForm::Iframe, "src")),
+            "object" => Some((EmbedForm::Object, "data")),
+            "embed" => Some((EmbedForm::Object, "src")),
+            "script" => Some((EmbedForm::Script, "src")),
+            "a" => Some((EmbedForm::Link, "href")),
+            _ => None,
+        }
+    }
+}
+
+/// How a member embeds the ring, which decides what their healthcheck looks for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbedKind {
+    /// The iframe from the README, or an object or embed tag.
+    #[default]
+    Iframe,
+    /// The `widget.js` script tag.
+    Script,
+    /// A link back to the ring, for sites that can't embed anything.
+    Link,
+}
+
+impl EmbedKind {
+    pub fn accepts(self, form: EmbedForm) -> bool {
+        match self {
+            EmbedKind::Iframe => matches!(form, EmbedForm::Iframe | EmbedForm::Object),
+            EmbedKind::Script => form == EmbedForm::Script,
+            EmbedKind::Link => form == EmbedForm::Link,
+        }
+    }
+}
+
+impl fmt::Display for EmbedKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbedKind::Iframe => write!(f, "iframe"),
+            EmbedKind::Script => write!(f, "script"),
+            EmbedKind::Link => write!(f, "link"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Visible,
+    /// Hidden with an attribute or inline style, on the embed or one of its parents.
+    Hidden,
+    CommentedOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct FoundEmbed {
+    pub form: EmbedForm,
+    /// The member the embed is for. Links back to the ring don't say.
+    pub slug: Option<String>,
+    /// Where the embed points, resolved against the page.
+    pub url: Url,
+    /// The tag's `nonce` attribute, which lets script embeds past a nonce-based CSP.
+    pub nonce: Option<String>,
+    pub visibility: Visibility,
+}
+
+/// Recognizes a ring's embed URLs, whatever scheme, trailing slash or query they're written with.
+pub struct EmbedMatcher {
+    host: String,
+    port: Option<u16>,
+    /// Path of the ring's home page, which links back have to be under.
+    base_path: String,
+    /// Path of the embeds, up to the slug.
+    embed_path: String,
+}
+
+impl EmbedMatcher {
+    /// `embed_prefix` is the ring's embed URL up to the slug, like
+    /// `https://overengineering.kognise.dev/embed/`.
+    pub fn new(embed_prefix: &Url) -> Self {
+        let embed_path = embed_prefix.path().to_string();
+        Self {
+            host: embed_prefix.host_str().unwrap_or_default().to_string(),
+            port: embed_prefix.port(),
+            base_path: embed_path.trim_end_matches("embed/").to_string(),
+            embed_path,
+        }
+    }
+
+    /// What `src` points at on the ring, if anything, when used in `form`, along with `src`
+    /// itself resolved against `page`, the page it was found on. The outer `None` means it isn't
+    /// an embed of this ring at all. Relative and protocol-relative URLs are fine.
+    pub fn slug_in(&self, form: EmbedForm, page: &Url, src: &str) -> Option<(Option<String>, Url)> {
+        let url = page.join(src.trim()).ok()?;
+        if !matches!(url.scheme(), "http" | "https")
+            || url.host_str() != Some(&self.host)
+            || url.port() != self.port
+        {
+            return None;
+        }
+
+        let path = url.path();
+        let slug = match form {
+            EmbedForm::Link => {
+                // Links to anywhere on the ring count, including without the trailing slash.
+                let on_ring = path.starts_with(&self.base_path)
+                    || self.base_path.trim_end_matches('/') == path;
+                return on_ring.then_some((None, url.clone()));
+            }
+            EmbedForm::Script => path
+                .strip_prefix(&self.embed_path)?
+                .strip_suffix("/widget.js")?,
+            EmbedForm::Iframe | EmbedForm::Object => {
+                path.strip_prefix(&self.embed_path)?.trim_end_matches('/')
+            }
+        };
+        if slug.is_empty() || slug.contains('/') {
+            return None;
+        }
+        Some((Some(slug.to_string()), url.clone()))
+    }
+
+    /// Every embed of this ring in `document`, the page at `page`, including hidden and
+    /// commented out ones.
+    pub fn find(&self, page: &Url, document: &Html) -> Vec<FoundEmbed> {
+        let mut found = vec![];
+        self.find_in(page, document, false, &mut found);
+        found
+    }
+
+    fn find_in(&self, page: &Url, html: &Html, in_comment: bool, found: &mut Vec<FoundEmbed>) {
+        for node in html.tree.nodes() {
+            match node.value() {
+                Node::Element(element) => {
+                    let (form, attr) = match EmbedForm::of(element) {
+                        Some(form) => form,
+                        None => continue,
+                    };
+                    let (slug, url) = match element
+                        .attr(attr)
+                        .and_then(|src| self.slug_in(form, page, src))
+                    {
+                        Some(found) => found,
+                        None => continue,
+                    };
+
+                    let is_hidden = is_hidden(element, true)
+                        || node
+                            .ancestors()
+                            .filter_map(|ancestor| ancestor.value().as_element())
+                            .any(|ancestor| is_hidden(ancestor, false));
+                    let visibility = if in_comment {
+                        Visibility::CommentedOut
+                    } else if is_hidden {
+                        Visibility::Hidden
+                    } else {
+                        Visibility::Visible
+                    };
+                    found.push(FoundEmbed {
+                        form,
+                        slug,
+                        url,
+                        nonce: element.attr("nonce").map(str::to_string),
+                        visibility,
+                    });
+                }
+                // Commented out markup is plain text to the parser, so parse it again to see
+                // whether there's an embed in there.
+                Node::Comment(comment) if !in_comment => {
+                    self.find_in(page, &Html::parse_fragment(comment), true, found)
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether an element is hidden by its own attributes or inline style. Zero sizes only count for
+/// the embed itself, since plenty of layouts use empty-looking wrappers.
+fn is_hidden(element: &Element, is_embed: bool) -> bool {
+    if element.attr("hidden").is_some() {
+        return true;
+    }
+    if is_embed
+        && ["width", "height"]
+            .iter()
+            .any(|attr| element.attr(attr).map(str::trim) == Some("0"))
+    {
+        return true;
+    }
+
+    let style: String = element
+        .attr("style")
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    style
+        .split(';')
+        .map(|declaration| declaration.trim_end_matches("!important"))
+        .any(|declaration| matches!(declaration, "display:none" | "visibility:hidden"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = "https://member.example/blog/";
+
+    fn matcher() -> EmbedMatcher {
+        EmbedMatcher::new(&Url::parse("https://ring.example/embed/").unwrap())
+    }
+
+    fn slug_in(form: EmbedForm, src: &str) -> Option<Option<String>> {
+        matcher()
+            .slug_in(form, &Url::parse(PAGE).unwrap(), src)
+            .map(|(slug, _)| slug)
+    }
+
+    fn find(body: &str) -> Vec<(EmbedForm, Option<String>, Visibility)> {
+        let document = Html::parse_document(&format!("<html><body>{}</body></html>", body));
+        matcher()
+            .find(&Url::parse(PAGE).unwrap(), &document)
+            .into_iter()
+            .map(|embed| (embed.form, embed.slug, embed.visibility))
+            .collect()
+    }
+
+    fn someone() -> Option<String> {
+        Some("someone".to_string())
+    }
+
+    #[test]
+    fn reads_slugs_from_any_spelling_of_the_embed_url() {
+        for src in [
+            "https://ring.example/embed/someone",
+            "http://ring.example/embed/someone",
+            "//ring.example/embed/someone",
+            "https://ring.example/embed/someone/",
+            "https://ring.example/embed/someone?theme=dark",
+            "  https://ring.example/embed/someone  ",
+        ] {
+            assert_eq!(slug_in(EmbedForm::Iframe, src), Some(someone()), "{}", src);
+        }
+        assert_eq!(
+            slug_in(EmbedForm::Script, "//ring.example/embed/someone/widget.js"),
+            Some(someone())
+        );
+    }
+
+    #[test]
+    fn rejects_other_urls() {
+        for src in [
+            "https://ring.example/embed/",
+            "https://ring.example/embed/someone/else",
+            "https://ring.example:8443/embed/someone",
+            "https://other.example/embed/someone",
+            "ftp://ring.example/embed/someone",
+            "/embed/someone",
+        ] {
+            assert_eq!(slug_in(EmbedForm::Iframe, src), None, "{}", src);
+        }
+        assert_eq!(
+            slug_in(EmbedForm::Script, "https://ring.example/embed/someone"),
+            None
+        );
+    }
+
+    #[test]
+    fn links_anywhere_on_the_ring_count() {
+        assert_eq!(
+            slug_in(EmbedForm::Link, "https://ring.example/"),
+            Some(None)
+        );
+        assert_eq!(slug_in(EmbedForm::Link, "https://ring.example"), Some(None));
+        assert_eq!(
+            slug_in(EmbedForm::Link, "https://ring.example/embed/someone"),
+            Some(None)
+        );
+        assert_eq!(slug_in(EmbedForm::Link, "https://other.example/"), None);
+    }
+
+    #[test]
+    fn finds_embeds_in_markup() {
+        assert_eq!(
+            find(r#"<iframe src="https://ring.example/embed/someone"></iframe>"#),
+            vec![(EmbedForm::Iframe, someone(), Visibility::Visible)]
+        );
+        assert_eq!(
+            find(
+                r#"<iframe src="https:&#x2F;&#x2F;ring.example&#x2F;embed&#x2F;someone?a=1&amp;b=2"></iframe>"#
+            ),
+            vec![(EmbedForm::Iframe, someone(), Visibility::Visible)]
+        );
+        assert_eq!(
+            find(r#"<object data="//ring.example/embed/someone/"></object>"#),
+            vec![(EmbedForm::Object, someone(), Visibility::Visible)]
+        );
+        assert_eq!(
+            find(r#"<a href="https://ring.example/">a webring</a>"#),
+            vec![(EmbedForm::Link, None, Visibility::Visible)]
+        );
+        assert_eq!(
+            find(r#"<img src="https://ring.example/embed/someone">"#),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn finds_commented_out_embeds() {
+        assert_eq!(
+            find(r#"<!-- <iframe src="https://ring.example/embed/someone"></iframe> -->"#),
+            vec![(EmbedForm::Iframe, someone(), Visibility::CommentedOut)]
+        );
+        // A bare URL in a comment isn't an embed.
+        assert_eq!(find("<!-- https://ring.example/embed/someone -->"), vec![]);
+    }
+
+    #[test]
+    fn finds_hidden_embeds() {
+        for body in [
+            r#"<iframe hidden src="https://ring.example/embed/someone"></iframe>"#,
+            r#"<iframe width="0" src="https://ring.example/embed/someone"></iframe>"#,
+            r#"<iframe height=" 0 " src="https://ring.example/embed/someone"></iframe>"#,
+            r#"<iframe style="Display: None !important" src="https://ring.example/embed/someone"></iframe>"#,
+            r#"<div style="visibility:hidden"><iframe src="https://ring.example/embed/someone"></iframe></div>"#,
+            r#"<div hidden><p><iframe src="https://ring.example/embed/someone"></iframe></p></div>"#,
+        ] {
+            assert_eq!(
+                find(body),
+                vec![(EmbedForm::Iframe, someone(), Visibility::Hidden)],
+                "{}",
+                body
+            );
+        }
+        // Zero-size wrappers are common enough not to count.
+        assert_eq!(
+            find(
+                r#"<div height="0"><iframe src="https://ring.example/embed/someone"></iframe></div>"#
+            ),
+            vec![(EmbedForm::Iframe, someone(), Visibility::Visible)]
+        );
+    }
+}
@@ -1,146 +1,1511 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    convert::TryInto,
+    error::Error,
+    fmt, io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use lazy_static::lazy_static;
-use reqwest::Client;
+use anyhow::bail;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{RunQueryDsl, SqliteConnection};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rand::{thread_rng, Rng};
+use reqwest::{
+    header::{
+        HeaderValue, ACCEPT, CONTENT_SECURITY_POLICY, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+        IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    redirect,
+    tls::TlsInfo,
+    Client, Response, StatusCode,
+};
 use rocket::{
     futures::{stream, StreamExt},
-    tokio::{spawn, sync::RwLock, task::JoinHandle, time::interval},
+    tokio::{
+        select, spawn,
+        sync::{
+            mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+            oneshot, watch, Mutex, Notify, RwLock,
+        },
+        task::JoinHandle,
+        time::sleep,
+    },
 };
-use serde::Serialize;
-
-use crate::config::{read_members, Member};
+use scraper::Html;
+use serde::{Serialize, Serializer};
+use url::Url;
+use x509_cert::{der::Decode, Certificate};
 
-lazy_static! {
-    static ref CLIENT: Client = Client::builder()
-        .timeout(Duration::from_secs(5))
-        .build()
-        .unwrap();
-}
+use crate::{
+    config::{read_members, Member, MemberStatus},
+    csp::{blocking_directive, Policy},
+    embed::{EmbedForm, EmbedKind, EmbedMatcher, FoundEmbed, Visibility},
+    models::{open_database, NewHealthCheck, Uptime},
+    order::RingOrder,
+    parking::{is_parked, is_same_site},
+    ring::{HealthcheckConfig, RingConfig},
+    schema::health_checks,
+    webhook::{Transition, Webhooks},
+};
 
-#[derive(Serialize, Clone)]
+#[derive(Clone)]
 pub enum Health {
-    Ok,
+    /// The page embeds the ring in this form.
+    Ok(EmbedForm),
+    /// The site's domain name didn't resolve.
+    DnsFailure,
+    ConnectionRefused,
+    /// The site didn't respond within the healthcheck timeout.
+    Timeout,
+    /// The TLS handshake failed, usually because of an expired or mismatched certificate.
+    TlsError,
+    TooManyRedirects,
+    /// The site responded with this non-2xx status code.
+    HttpError(u16),
+    /// The page started loading but its body couldn't be read.
+    BodyError,
+    /// No embed in as much of the page as we're willing to download, this many bytes.
+    PageTooLarge(usize),
+    /// Couldn't reach the site for some other reason.
     SiteUnreachable,
+    /// The domain is parked or up for sale, going by the page at this URL.
+    DomainParked(String),
+    /// The page redirects to this URL on another site, probably because the member moved.
+    RedirectedAway(String),
     SlugMismatch(String),
+    /// The embed is on the page, but hidden from visitors.
+    EmbedHidden(EmbedForm),
+    /// The embed is only on the page inside an HTML comment.
+    EmbedCommentedOut,
+    /// The embed is on the page, but the page's content security policy doesn't let it load,
+    /// because of this directive.
+    EmbedBlockedByCsp(String),
     NoWebringEmbed,
 }
 
-pub async fn check_health(url: &str, slug: &str) -> Health {
-    let response = match CLIENT.get(url).send().await {
-        Ok(body) => body,
-        Err(_) => return Health::SiteUnreachable,
-    };
-    let body = match response.text().await {
-        Ok(body) => body,
-        Err(_) => return Health::SiteUnreachable,
-    };
+/// Written out like serde would, except that `Ok` stays a plain `"Ok"` as it was before embed
+/// forms were tracked, so existing `/members.json` consumers keep working. The form is available
+/// from [`Health::embed_form`] instead.
+impl Serialize for Health {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Health::Ok(_) => serializer.serialize_unit_variant("Health", 0, "Ok"),
+            Health::DnsFailure => serializer.serialize_unit_variant("Health", 1, "DnsFailure"),
+            Health::ConnectionRefused => {
+                serializer.serialize_unit_variant("Health", 2, "ConnectionRefused")
+            }
+            Health::Timeout => serializer.serialize_unit_variant("Health", 3, "Timeout"),
+            Health::TlsError => serializer.serialize_unit_variant("Health", 4, "TlsError"),
+            Health::TooManyRedirects => {
+                serializer.serialize_unit_variant("Health", 5, "TooManyRedirects")
+            }
+            Health::HttpError(status) => {
+                serializer.serialize_newtype_variant("Health", 6, "HttpError", status)
+            }
+            Health::BodyError => serializer.serialize_unit_variant("Health", 7, "BodyError"),
+            Health::PageTooLarge(size) => {
+                serializer.serialize_newtype_variant("Health", 8, "PageTooLarge", size)
+            }
+            Health::SiteUnreachable => {
+                serializer.serialize_unit_variant("Health", 9, "SiteUnreachable")
+            }
+            Health::DomainParked(url) => {
+                serializer.serialize_newtype_variant("Health", 10, "DomainParked", url)
+            }
+            Health::RedirectedAway(url) => {
+                serializer.serialize_newtype_variant("Health", 11, "RedirectedAway", url)
+            }
+            Health::SlugMismatch(slug) => {
+                serializer.serialize_newtype_variant("Health", 12, "SlugMismatch", slug)
+            }
+            Health::EmbedHidden(form) => {
+                serializer.serialize_newtype_variant("Health", 13, "EmbedHidden", form)
+            }
+            Health::EmbedCommentedOut => {
+                serializer.serialize_unit_variant("Health", 14, "EmbedCommentedOut")
+            }
+            Health::EmbedBlockedByCsp(directive) => {
+                serializer.serialize_newtype_variant("Health", 15, "EmbedBlockedByCsp", directive)
+            }
+            Health::NoWebringEmbed => {
+                serializer.serialize_unit_variant("Health", 16, "NoWebringEmbed")
+            }
+        }
+    }
+}
+
+impl Health {
+    /// The form the ring is embedded in, for passing checks.
+    pub fn embed_form(&self) -> Option<EmbedForm> {
+        match self {
+            Health::Ok(form) => Some(*form),
+            _ => None,
+        }
+    }
+
+    /// The kind of failure as stored in the healthcheck history, or `None` for a passing check.
+    pub fn failure_kind(&self) -> Option<&'static str> {
+        match self {
+            Health::Ok(_) => None,
+            Health::DnsFailure => Some("dns_failure"),
+            Health::ConnectionRefused => Some("connection_refused"),
+            Health::Timeout => Some("timeout"),
+            Health::TlsError => Some("tls_error"),
+            Health::TooManyRedirects => Some("too_many_redirects"),
+            Health::HttpError(_) => Some("http_error"),
+            Health::BodyError => Some("body_error"),
+            Health::PageTooLarge(_) => Some("page_too_large"),
+            Health::SiteUnreachable => Some("site_unreachable"),
+            Health::DomainParked(_) => Some("domain_parked"),
+            Health::RedirectedAway(_) => Some("redirected_away"),
+            Health::SlugMismatch(_) => Some("slug_mismatch"),
+            Health::EmbedHidden(_) => Some("embed_hidden"),
+            Health::EmbedCommentedOut => Some("embed_commented_out"),
+            Health::EmbedBlockedByCsp(_) => Some("embed_blocked_by_csp"),
+            Health::NoWebringEmbed => Some("no_webring_embed"),
+        }
+    }
+
+    /// Where the member's page actually ended up, when that's why the check failed.
+    pub fn destination(&self) -> Option<&str> {
+        match self {
+            Health::DomainParked(url) | Health::RedirectedAway(url) => Some(url),
+            _ => None,
+        }
+    }
+
+    /// `ok`, or the kind of failure. Webhooks are sent when this changes.
+    pub fn state(&self) -> &'static str {
+        self.failure_kind().unwrap_or("ok")
+    }
 
-    const URL_PREFIX: &str = "https://overengineering.kognise.dev/embed/";
-    let mut correct_count = 0;
-    let mut incorrect_slugs: Vec<String> = vec![];
-    for (offset, _) in body.match_indices(URL_PREFIX) {
-        let offset = offset + URL_PREFIX.len();
+    /// Whether the failure might go away by itself, making the check worth retrying.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Health::HttpError(status) => *status >= 500 || *status == 429,
+            Health::DnsFailure
+            | Health::ConnectionRefused
+            | Health::Timeout
+            | Health::BodyError
+            | Health::SiteUnreachable => true,
+            _ => false,
+        }
+    }
 
-        let mut body_slug = String::with_capacity(slug.len());
-        for char in body[offset..].chars() {
-            if char == '\'' || char == '"' || char == '?' || body_slug.len() >= 64 {
+    /// Works out why a request to a member site failed.
+    fn from_error(err: &reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return Health::Timeout;
+        }
+        if err.is_redirect() {
+            return Health::TooManyRedirects;
+        }
+        if err.is_body() || err.is_decode() {
+            return Health::BodyError;
+        }
+
+        // The underlying cause is buried in hyper's and the TLS library's own error types, which
+        // mostly only say what happened in their messages.
+        let mut source = err.source();
+        while let Some(cause) = source {
+            if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+                match io_err.kind() {
+                    io::ErrorKind::ConnectionRefused => return Health::ConnectionRefused,
+                    io::ErrorKind::TimedOut => return Health::Timeout,
+                    _ => {}
+                }
+            }
+            let message = cause.to_string().to_lowercase();
+            if message.contains("dns error") || message.contains("failed to lookup address") {
+                return Health::DnsFailure;
+            }
+            if ["certificate", "ssl", "tls", "handshake"]
+                .iter()
+                .any(|word| message.contains(word))
+            {
+                return Health::TlsError;
+            }
+            source = cause.source();
+        }
+        Health::SiteUnreachable
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::Ok(_) => write!(f, "ok"),
+            Health::DnsFailure => write!(f, "domain name doesn't resolve"),
+            Health::ConnectionRefused => write!(f, "connection refused"),
+            Health::Timeout => write!(f, "site timed out"),
+            Health::TlsError => write!(f, "tls error, check the certificate"),
+            Health::TooManyRedirects => write!(f, "too many redirects"),
+            Health::HttpError(status) => match StatusCode::from_u16(*status)
+                .ok()
+                .and_then(|status| status.canonical_reason())
+            {
+                Some(reason) => write!(f, "http {} {}", status, reason.to_lowercase()),
+                None => write!(f, "http {}", status),
+            },
+            Health::BodyError => write!(f, "couldn't read the page"),
+            Health::PageTooLarge(limit) => {
+                write!(f, "no embed in the first {} kb of the page", limit / 1024)
+            }
+            Health::SiteUnreachable => write!(f, "site unreachable"),
+            Health::DomainParked(_) => write!(f, "domain is parked or for sale"),
+            Health::RedirectedAway(_) => write!(f, "redirects to another site"),
+            Health::SlugMismatch(slug) => write!(f, "embed url has wrong slug {:?}", slug),
+            Health::EmbedHidden(_) => write!(f, "embed is hidden"),
+            Health::EmbedCommentedOut => write!(f, "embed is commented out"),
+            Health::EmbedBlockedByCsp(directive) => {
+                write!(
+                    f,
+                    "embed blocked by content security policy {:?}",
+                    directive
+                )
+            }
+            Health::NoWebringEmbed => write!(f, "embed missing from site"),
+        }
+    }
+}
+
+/// Something wrong with a member's setup that doesn't keep them out of the ring.
+#[derive(Serialize, Clone, Debug)]
+pub enum Warning {
+    /// A stylesheet from the member's config that the embed can't use, and what's wrong with it.
+    BrokenStylesheet { url: String, problem: String },
+    /// The site's TLS certificate runs out soon, and the site will be unreachable once it does.
+    CertificateExpiring { expires_at: DateTime<Utc> },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::BrokenStylesheet { url, problem } => {
+                write!(f, "stylesheet {} {}", url, problem)
+            }
+            Warning::CertificateExpiring { expires_at } if *expires_at <= Utc::now() => write!(
+                f,
+                "tls certificate expired on {}",
+                expires_at.format("%Y-%m-%d")
+            ),
+            Warning::CertificateExpiring { expires_at } => write!(
+                f,
+                "tls certificate expires in {} days, on {}",
+                (*expires_at - Utc::now()).num_days(),
+                expires_at.format("%Y-%m-%d")
+            ),
+        }
+    }
+}
+
+/// What the last healthcheck found out about a member, besides whether they pass.
+#[derive(Serialize, Clone, Default)]
+pub struct CheckDetails {
+    /// When the TLS certificate of the member's page runs out, for https sites.
+    pub certificate_expires_at: Option<DateTime<Utc>>,
+    pub warnings: Vec<Warning>,
+}
+
+impl CheckDetails {
+    /// `valid`, `expiring` or `expired`, as sent in certificate webhooks, if a certificate was
+    /// seen.
+    pub fn certificate_state(&self) -> Option<&'static str> {
+        if self.certificate_expires_at? <= Utc::now() {
+            return Some("expired");
+        }
+        let expiring = self
+            .warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::CertificateExpiring { .. }));
+        Some(if expiring { "expiring" } else { "valid" })
+    }
+}
+
+/// What a healthcheck needs to know about a member page.
+#[derive(Clone)]
+struct FetchedPage {
+    /// Where the page ended up after any redirects.
+    url: Url,
+    embeds: Vec<FoundEmbed>,
+    /// Content security policies from both headers and `<meta>` tags.
+    policies: Vec<Policy>,
+    /// Whether the page was cut short for being too big.
+    truncated: bool,
+    /// Whether the page looks like a parking page rather than the member's site.
+    parked: bool,
+}
+
+/// What we remember about a member page, so it's only downloaded again once it has changed.
+struct CachedPage {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    page: FetchedPage,
+}
+
+/// The HTTP side of healthchecks, trying to go easy on small personal sites: it says who it is,
+/// gives up on huge pages and endless redirects, and asks for pages only if they've changed.
+pub struct HealthClient {
+    client: Client,
+    /// Accepts any certificate, so the expiry of one `client` refused can still be read.
+    inspector: Client,
+    max_body_size: usize,
+    pages: std::sync::Mutex<HashMap<String, CachedPage>>,
+    stylesheet_interval: Duration,
+    max_stylesheet_size: usize,
+    /// When each stylesheet was last checked, and what was wrong with it.
+    stylesheets: std::sync::Mutex<HashMap<String, (Instant, Option<String>)>>,
+    /// When the certificate of each page was found to run out, the last time it was loaded.
+    certificates: std::sync::Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl HealthClient {
+    pub fn new(config: &RingConfig) -> Self {
+        let client = Client::builder()
+            .user_agent(config.user_agent())
+            .timeout(config.healthcheck.timeout())
+            .redirect(redirect::Policy::limited(config.healthcheck.max_redirects))
+            .tls_info(true)
+            .build()
+            .unwrap();
+        let inspector = Client::builder()
+            .user_agent(config.user_agent())
+            .timeout(config.healthcheck.timeout())
+            .redirect(redirect::Policy::none())
+            .danger_accept_invalid_certs(true)
+            .tls_info(true)
+            .build()
+            .unwrap();
+        Self {
+            client,
+            inspector,
+            max_body_size: config.healthcheck.max_body_size,
+            pages: std::sync::Mutex::new(HashMap::new()),
+            stylesheet_interval: config.healthcheck.stylesheet_interval(),
+            max_stylesheet_size: config.healthcheck.max_stylesheet_size,
+            stylesheets: std::sync::Mutex::new(HashMap::new()),
+            certificates: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forgets cached pages and certificates for anything but `urls`, the pages still being
+    /// checked, and stylesheet results that are due to be checked again anyway.
+    fn retain(&self, urls: &[&str]) {
+        self.pages
+            .lock()
+            .unwrap()
+            .retain(|url, _| urls.contains(&url.as_str()));
+        self.certificates
+            .lock()
+            .unwrap()
+            .retain(|url, _| urls.contains(&url.as_str()));
+        self.stylesheets
+            .lock()
+            .unwrap()
+            .retain(|_, (checked_at, _)| checked_at.elapsed() < self.stylesheet_interval);
+    }
+
+    /// When the TLS certificate of the page at `url` runs out, as of the last time it loaded.
+    pub fn certificate_expiry(&self, url: &str) -> Option<DateTime<Utc>> {
+        self.certificates.lock().unwrap().get(url).copied()
+    }
+
+    /// Notes when the certificate `response` came with runs out, if it came over TLS.
+    fn record_certificate(&self, url: &str, response: &Response) {
+        if let Some(expires_at) = response
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(TlsInfo::peer_certificate)
+            .and_then(certificate_expiry)
+        {
+            self.certificates
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), expires_at);
+        }
+    }
+
+    /// What's wrong with the stylesheet at `url`, if anything. Results are reused for
+    /// `stylesheet_interval`, since the same font CDNs show up in lots of members' configs.
+    async fn stylesheet_problem(&self, url: &str) -> Option<String> {
+        if let Some((checked_at, problem)) = self.stylesheets.lock().unwrap().get(url) {
+            if checked_at.elapsed() < self.stylesheet_interval {
+                return problem.clone();
+            }
+        }
+
+        let problem = self.fetch_stylesheet(url).await.err();
+        self.stylesheets
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), (Instant::now(), problem.clone()));
+        problem
+    }
+
+    async fn fetch_stylesheet(&self, url: &str) -> Result<(), String> {
+        let describe =
+            |err: reqwest::Error| format!("can't be loaded: {}", Health::from_error(&err));
+        let mut response = self
+            .client
+            .get(url)
+            .header(ACCEPT, "text/css,*/*;q=0.1")
+            .send()
+            .await
+            .map_err(describe)?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "returns {}",
+                Health::HttpError(response.status().as_u16())
+            ));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        // Browsers refuse to apply stylesheets served as anything else.
+        if !mime.eq_ignore_ascii_case("text/css") {
+            return Err(format!("is served as {:?} instead of text/css", mime));
+        }
+
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await.map_err(describe)? {
+            size += chunk.len();
+            if size > self.max_stylesheet_size {
+                return Err(format!("is over {} kb", self.max_stylesheet_size / 1024));
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads the page at `url` and finds the ring's embeds on it. Unchanged pages are
+    /// answered from the cache.
+    async fn fetch_page(&self, matcher: &EmbedMatcher, url: &str) -> Result<FetchedPage, Health> {
+        self.certificates.lock().unwrap().remove(url);
+        let mut request = self.client.get(url);
+        if let Some(page) = self.pages.lock().unwrap().get(url) {
+            if let Some(etag) = &page.etag {
+                request = request.header(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = &page.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let mut response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => {
+                let health = Health::from_error(&err);
+                // Expired certificates fail the handshake, so they're looked at separately.
+                if matches!(health, Health::TlsError) {
+                    if let Ok(response) = self.inspector.head(url).send().await {
+                        self.record_certificate(url, &response);
+                    }
+                }
+                return Err(health);
+            }
+        };
+        self.record_certificate(url, &response);
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = self.pages.lock().unwrap().get(url) {
+                return Ok(cached.page.clone());
+            }
+        }
+        if !response.status().is_success() {
+            return Err(Health::HttpError(response.status().as_u16()));
+        }
+
+        // Relative embed URLs are relative to wherever any redirects ended up.
+        let page = response.url().clone();
+        let etag = response.headers().get(ETAG).cloned();
+        let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+        // Report-only policies don't block anything, so they're left out.
+        let mut policies: Vec<Policy> = response
+            .headers()
+            .get_all(CONTENT_SECURITY_POLICY)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(Policy::parse_header)
+            .collect();
+        let mut body = vec![];
+        let mut truncated = false;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|err| Health::from_error(&err))?
+        {
+            body.extend_from_slice(&chunk);
+            if body.len() > self.max_body_size {
+                body.truncate(self.max_body_size);
+                truncated = true;
                 break;
             }
-            body_slug.push(char);
         }
 
-        if body_slug != slug {
-            incorrect_slugs.push(body_slug);
+        let document = Html::parse_document(&String::from_utf8_lossy(&body));
+        policies.extend(Policy::from_meta(&document));
+        let page = FetchedPage {
+            embeds: matcher.find(&page, &document),
+            parked: is_parked(&page, &document),
+            url: page,
+            policies,
+            truncated,
+        };
+        let mut pages = self.pages.lock().unwrap();
+        if etag.is_some() || last_modified.is_some() {
+            pages.insert(
+                url.to_string(),
+                CachedPage {
+                    etag,
+                    last_modified,
+                    page: page.clone(),
+                },
+            );
+        } else {
+            pages.remove(url);
+        }
+        Ok(page)
+    }
+}
+
+/// When a DER encoded certificate runs out.
+fn certificate_expiry(der: &[u8]) -> Option<DateTime<Utc>> {
+    let certificate = Certificate::from_der(der).ok()?;
+    let not_after = certificate.tbs_certificate.validity.not_after;
+    let secs = not_after.to_unix_duration().as_secs().try_into().ok()?;
+    DateTime::from_timestamp(secs, 0)
+}
+
+/// Checks that the member's page visibly embeds the ring with their slug or one of their aliases,
+/// in the form they said they use.
+pub async fn check_health(
+    client: &HealthClient,
+    matcher: &EmbedMatcher,
+    member: &Member,
+) -> Health {
+    let page = match client.fetch_page(matcher, member.healthcheck_url()).await {
+        Ok(page) => page,
+        Err(health) => return health,
+    };
+    // A lapsed domain can still serve a cached copy of the old site, embed and all, so these
+    // come before looking for the embed.
+    if page.parked {
+        return Health::DomainParked(page.url.to_string());
+    }
+    let requested_host = Url::parse(member.healthcheck_url())
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+    if let (Some(requested), Some(landed)) = (requested_host, page.url.host_str()) {
+        if !is_same_site(&requested, landed) {
+            return Health::RedirectedAway(page.url.to_string());
+        }
+    }
+
+    let embeds: Vec<FoundEmbed> = page
+        .embeds
+        .into_iter()
+        .filter(|embed| member.embed_kind.accepts(embed.form))
+        .collect();
+    let is_own = |embed: &&FoundEmbed| {
+        embed
+            .slug
+            .as_deref()
+            .is_none_or(|slug| member.answers_to(slug))
+    };
+    let own = |visibility: Visibility| {
+        embeds
+            .iter()
+            .filter(is_own)
+            .find(|embed| embed.visibility == visibility)
+    };
+    // Visible embeds only count if the page's content security policy lets them load.
+    let mut blocked = None;
+    for embed in embeds
+        .iter()
+        .filter(is_own)
+        .filter(|embed| embed.visibility == Visibility::Visible)
+    {
+        match blocking_directive(&page.policies, embed, &page.url) {
+            Some(directive) => blocked = blocked.or(Some(directive)),
+            None => return Health::Ok(embed.form),
+        }
+    }
+    if let Some(directive) = blocked {
+        return Health::EmbedBlockedByCsp(directive);
+    }
+    if let Some(embed) = own(Visibility::Hidden) {
+        return Health::EmbedHidden(embed.form);
+    }
+    if own(Visibility::CommentedOut).is_some() {
+        return Health::EmbedCommentedOut;
+    }
+    match embeds
+        .into_iter()
+        .filter(|embed| embed.visibility == Visibility::Visible)
+        .find_map(|embed| embed.slug)
+    {
+        Some(slug) => Health::SlugMismatch(slug),
+        // The embed might be further down than we're willing to read.
+        None if page.truncated => Health::PageTooLarge(client.max_body_size),
+        None => Health::NoWebringEmbed,
+    }
+}
+
+/// Checks that the member's stylesheets load. Problems are only warnings, since the embed still
+/// works without them, just slower or in the default font.
+pub async fn check_stylesheets(client: &HealthClient, member: &Member) -> Vec<Warning> {
+    let mut warnings = vec![];
+    for url in &member.stylesheets {
+        if let Some(problem) = client.stylesheet_problem(url).await {
+            warnings.push(Warning::BrokenStylesheet {
+                url: url.clone(),
+                problem,
+            });
+        }
+    }
+    warnings
+}
+
+/// Looks into everything about a member's setup that's worth a warning, after their healthcheck
+/// has loaded their page.
+pub async fn check_details(
+    client: &HealthClient,
+    member: &Member,
+    config: &HealthcheckConfig,
+) -> CheckDetails {
+    let certificate_expires_at = client.certificate_expiry(member.healthcheck_url());
+    let mut warnings = check_stylesheets(client, member).await;
+    if let Some(expires_at) = certificate_expires_at {
+        if expires_at - Utc::now() <= chrono::Duration::days(config.certificate_warning_days) {
+            warnings.push(Warning::CertificateExpiring { expires_at });
+        }
+    }
+    CheckDetails {
+        certificate_expires_at,
+        warnings,
+    }
+}
+
+/// Checks a member, retrying with exponential backoff while their site is failing in a way that
+/// might fix itself. Returns the result along with how long the last attempt took.
+pub async fn check_health_with_retries(
+    client: &HealthClient,
+    matcher: &EmbedMatcher,
+    member: &Member,
+    config: &HealthcheckConfig,
+) -> (Health, Duration) {
+    let mut backoff = config.retry_backoff();
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        let health = check_health(client, matcher, member).await;
+        let latency = started.elapsed();
+        if !health.is_transient() || attempt >= config.retries {
+            return (health, latency);
+        }
+
+        attempt += 1;
+        sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// How many checks in a row a member has passed or failed.
+#[derive(Default)]
+struct Streak {
+    passed: u32,
+    failed: u32,
+}
+
+impl Streak {
+    /// Counts a new check result and decides the member's health from it, only moving them in or
+    /// out of the ring once enough results in a row agree.
+    fn settle(
+        &mut self,
+        previous: Option<&Health>,
+        observed: Health,
+        config: &HealthcheckConfig,
+    ) -> Health {
+        if matches!(observed, Health::Ok(_)) {
+            self.passed = self.passed.saturating_add(1);
+            self.failed = 0;
         } else {
-            correct_count += 1;
+            self.failed = self.failed.saturating_add(1);
+            self.passed = 0;
+        }
+
+        match previous {
+            Some(previous @ Health::Ok(_))
+                if self.failed > 0 && self.failed < config.failures_to_drop =>
+            {
+                previous.clone()
+            }
+            Some(previous)
+                if !matches!(previous, Health::Ok(_))
+                    && self.passed > 0
+                    && self.passed < config.successes_to_return =>
+            {
+                previous.clone()
+            }
+            // A member's first check counts straight away, there's nothing to smooth over yet.
+            _ => observed,
         }
     }
 
-    if correct_count == 0 {
-        match incorrect_slugs.pop() {
-            Some(slug) => Health::SlugMismatch(slug),
-            None => Health::NoWebringEmbed,
+    /// How long to wait before the next check. Once a member has failed enough to leave the
+    /// ring, the wait doubles with every further failure, up to `max_backoff`. Up to a tenth
+    /// more is added at random so checks spread out instead of all landing at once.
+    fn delay(&self, config: &HealthcheckConfig) -> Duration {
+        let backoffs = self.failed.saturating_sub(config.failures_to_drop).min(16);
+        let delay = config
+            .interval()
+            .saturating_mul(1 << backoffs)
+            .min(config.max_backoff())
+            .max(config.interval());
+        delay.mul_f64(1.0 + thread_rng().gen_range(0.0..0.1))
+    }
+}
+
+/// The outcome of a recheck a member asked for.
+#[derive(Serialize, Clone)]
+pub struct Recheck {
+    pub slug: String,
+    pub name: String,
+    pub url: String,
+    pub embed_kind: EmbedKind,
+    pub checked_at: DateTime<Utc>,
+    pub latency_ms: u128,
+    /// What this check found, even if it isn't enough to move the member in or out of the ring.
+    pub health: Health,
+    pub embed_form: Option<EmbedForm>,
+    pub ok: bool,
+    pub reason: String,
+    /// Whether the member is in the ring after this check, which a single failure doesn't change.
+    pub in_ring: bool,
+    #[serde(flatten)]
+    pub details: CheckDetails,
+}
+
+pub enum RecheckError {
+    /// Nobody in the ring goes by that slug.
+    UnknownMember,
+    /// The member is on hiatus or being removed, so they aren't being checked.
+    NotChecked,
+    /// The member was rechecked too recently, and can try again after this long.
+    TooSoon(Duration),
+}
+
+type RecheckReply = oneshot::Sender<Option<Recheck>>;
+
+/// When a member is next due for a healthcheck, and how their last few went.
+struct Schedule {
+    member: Member,
+    due: Instant,
+    streak: Streak,
+    /// Whether the next result should count straight away if it passes instead of waiting for a
+    /// streak, because the member's config changed or they asked for a recheck.
+    fresh: bool,
+}
+
+impl Schedule {
+    fn new(member: Member, due: Instant) -> Self {
+        Self {
+            member,
+            due,
+            streak: Streak::default(),
+            fresh: false,
         }
-    } else {
-        Health::Ok
     }
 }
 
+/// How the last attempts to read the members directory went, for operators.
+#[derive(Serialize, Clone, Default)]
+pub struct LoadStatus {
+    pub member_count: usize,
+    pub last_loaded_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    pub watching: bool,
+}
+
 pub struct MemberManager {
+    config: Arc<RingConfig>,
     members: Arc<RwLock<Vec<Member>>>,
     health: Arc<RwLock<HashMap<String, Health>>>,
+    details: Arc<RwLock<HashMap<String, CheckDetails>>>,
+    uptime: Arc<RwLock<HashMap<String, Uptime>>>,
+    status: Arc<RwLock<LoadStatus>>,
+    generation: Arc<watch::Sender<u64>>,
+    reload_trigger: Arc<Notify>,
+    rechecks: UnboundedSender<(String, RecheckReply)>,
+    last_rechecks: std::sync::Mutex<HashMap<String, Instant>>,
+    watch_handle: JoinHandle<()>,
     interval_handle: JoinHandle<()>,
 }
 
 impl Default for MemberManager {
     fn default() -> Self {
-        Self::new()
+        let config = RingConfig::default();
+        let db = open_database(&config.database.to_string_lossy()).expect("couldn't open database");
+        Self::new(config, Arc::new(Mutex::new(db)))
     }
 }
 
 impl MemberManager {
-    pub fn new() -> Self {
+    /// Starts loading and healthchecking the ring's members. Healthcheck results are recorded
+    /// in `db`.
+    pub fn new(config: RingConfig, db: Arc<Mutex<SqliteConnection>>) -> Self {
+        let config = Arc::new(config);
         let members = Arc::new(RwLock::new(vec![]));
         let health = Arc::new(RwLock::new(HashMap::new()));
+        let details = Arc::new(RwLock::new(HashMap::new()));
+        let uptime = Arc::new(RwLock::new(HashMap::new()));
+        let status = Arc::new(RwLock::new(LoadStatus::default()));
+        let generation = Arc::new(watch::channel(0).0);
+        let reload_trigger = Arc::new(Notify::new());
+        let (rechecks, recheck_requests) = unbounded_channel();
 
-        let interval_handle = spawn(Self::health_check_task(members.clone(), health.clone()));
+        let watch_handle = spawn(Self::watch_task(
+            config.clone(),
+            members.clone(),
+            status.clone(),
+            generation.clone(),
+            reload_trigger.clone(),
+        ));
+        let interval_handle = spawn(Self::health_check_task(
+            config.clone(),
+            members.clone(),
+            health.clone(),
+            details.clone(),
+            uptime.clone(),
+            db,
+            generation.subscribe(),
+            recheck_requests,
+        ));
 
         Self {
+            config,
             members,
             health,
+            details,
+            uptime,
+            status,
+            generation,
+            reload_trigger,
+            rechecks,
+            last_rechecks: std::sync::Mutex::new(HashMap::new()),
+            watch_handle,
             interval_handle,
         }
     }
 
+    pub fn config(&self) -> &RingConfig {
+        &self.config
+    }
+
+    pub fn order(&self) -> &RingOrder {
+        &self.config.order
+    }
+
+    /// Every member with their latest health, in today's ring order.
     pub async fn members(&self) -> Vec<(Member, Option<Health>)> {
-        *self.members.write().await = read_members().await.expect("failed to read members");
+        self.members_on(self.order().today()).await
+    }
+
+    /// Every member with their latest health, in ring order and with statuses resolved for `date`.
+    pub async fn members_on(&self, date: NaiveDate) -> Vec<(Member, Option<Health>)> {
+        let mut members = self.members.read().await.clone();
+        self.order().sort(&mut members, date);
+
         let health = self.health.read().await;
-        self.members
+        members
+            .into_iter()
+            .map(|mut member| {
+                member.status = member.status.on(date);
+                let health = health.get(&member.slug).cloned();
+                (member, health)
+            })
+            .collect()
+    }
+
+    /// Whether a member is linked to from the ring. Everything that decides who shows up on
+    /// embeds, `/rand`, and the member list goes through here.
+    pub fn is_in_ring(member: &Member, health: Option<&Health>) -> bool {
+        member.status == MemberStatus::Active && matches!(health, Some(Health::Ok(_)))
+    }
+
+    /// Members currently in the ring, in ring order.
+    pub async fn ring(&self) -> Vec<Member> {
+        self.members()
+            .await
+            .into_iter()
+            .filter(|(member, health)| Self::is_in_ring(member, health.as_ref()))
+            .map(|(member, _)| member)
+            .collect()
+    }
+
+    /// What the last healthchecks found out about members besides whether they pass, by slug.
+    pub async fn details(&self) -> HashMap<String, CheckDetails> {
+        self.details.read().await.clone()
+    }
+
+    /// Uptime percentages for every member with healthcheck history, by slug, as of the last
+    /// round of healthchecks.
+    pub async fn uptime(&self) -> HashMap<String, Uptime> {
+        self.uptime.read().await.clone()
+    }
+
+    pub async fn status(&self) -> LoadStatus {
+        self.status.read().await.clone()
+    }
+
+    /// Re-reads the members directory right away, for when the filesystem watcher isn't available.
+    pub async fn reload(&self) {
+        Self::load_members(
+            &self.config.members_dir,
+            &self.members,
+            &self.status,
+            &self.generation,
+        )
+        .await;
+    }
+
+    /// Asks the background watcher to re-read the members directory without waiting for it.
+    pub fn request_reload(&self) {
+        self.reload_trigger.notify_one();
+    }
+
+    /// Checks a member right away instead of waiting for their next scheduled check, for when
+    /// they've just fixed their embed. The result counts immediately, and each member can only
+    /// ask once per `recheck_cooldown`.
+    pub async fn recheck(&self, slug: &str) -> Result<Recheck, RecheckError> {
+        let slug = match self
+            .members
             .read()
             .await
             .iter()
-            .map(|member| (member.clone(), health.get(&member.slug).cloned()))
-            .collect()
+            .find(|member| member.answers_to(slug))
+        {
+            Some(member) => member.slug.clone(),
+            None => return Err(RecheckError::UnknownMember),
+        };
+
+        {
+            let mut last_rechecks = self.last_rechecks.lock().unwrap();
+            let cooldown = self.config.healthcheck.recheck_cooldown();
+            last_rechecks.retain(|_, at| at.elapsed() < cooldown);
+            if let Some(at) = last_rechecks.get(&slug) {
+                return Err(RecheckError::TooSoon(cooldown.saturating_sub(at.elapsed())));
+            }
+            last_rechecks.insert(slug.clone(), Instant::now());
+        }
+
+        // The cooldown is taken up front so simultaneous requests can't all get through, and
+        // handed back if the member didn't end up checked.
+        let (reply, result) = oneshot::channel();
+        let recheck = match self.rechecks.send((slug.clone(), reply)) {
+            Ok(()) => result.await.ok().flatten(),
+            Err(_) => None,
+        };
+        if recheck.is_none() {
+            self.last_rechecks.lock().unwrap().remove(&slug);
+        }
+        recheck.ok_or(RecheckError::NotChecked)
     }
 
+    /// Swaps in a freshly read member list, or keeps serving the last good one if reading fails.
+    /// Returns whether the read succeeded.
+    async fn load_members(
+        members_dir: &Path,
+        members: &RwLock<Vec<Member>>,
+        status: &RwLock<LoadStatus>,
+        generation: &watch::Sender<u64>,
+    ) -> bool {
+        let result = read_members(members_dir).await.and_then(|new_members| {
+            if new_members.is_empty() {
+                bail!("no members found");
+            }
+            Ok(new_members)
+        });
+
+        match result {
+            Ok(new_members) => {
+                let mut status = status.write().await;
+                if status.last_error.take().is_some() {
+                    println!(
+                        "Members directory readable again, loaded {} members",
+                        new_members.len()
+                    );
+                }
+                status.member_count = new_members.len();
+                status.last_loaded_at = Some(Utc::now());
+
+                *members.write().await = new_members;
+                generation.send_modify(|generation| *generation += 1);
+                true
+            }
+            Err(err) => {
+                eprintln!("failed to read members, keeping previous list: {:#}", err);
+                let mut status = status.write().await;
+                status.last_error = Some(format!("{:#}", err));
+                status.last_error_at = Some(Utc::now());
+                false
+            }
+        }
+    }
+
+    fn watch_members(
+        members_dir: &Path,
+        events: UnboundedSender<()>,
+    ) -> notify::Result<RecommendedWatcher> {
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_ok_and(|event| !event.kind.is_access()) {
+                    let _ = events.send(());
+                }
+            })?;
+        watcher.watch(members_dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    }
+
+    async fn watch_task(
+        config: Arc<RingConfig>,
+        members: Arc<RwLock<Vec<Member>>>,
+        status: Arc<RwLock<LoadStatus>>,
+        generation: Arc<watch::Sender<u64>>,
+        reload_trigger: Arc<Notify>,
+    ) {
+        let (events_tx, mut events) = unbounded_channel();
+        // Dropping the watcher stops it, so it has to live as long as this task.
+        let mut watcher = None;
+
+        loop {
+            if watcher.is_none() {
+                watcher = match Self::watch_members(&config.members_dir, events_tx.clone()) {
+                    Ok(watcher) => Some(watcher),
+                    Err(err) => {
+                        eprintln!("couldn't watch members directory, retrying soon: {}", err);
+                        None
+                    }
+                };
+            }
+
+            // A directory that disappeared takes its watch with it, so start over once it's back.
+            if !Self::load_members(&config.members_dir, &members, &status, &generation).await {
+                watcher = None;
+            }
+            status.write().await.watching = watcher.is_some();
+
+            select! {
+                Some(()) = events.recv() => {
+                    // Editors and rsync touch files several times in a row, so wait for them to settle.
+                    sleep(Duration::from_millis(250)).await;
+                    while events.try_recv().is_ok() {}
+                }
+                _ = reload_trigger.notified() => {}
+                _ = sleep(Duration::from_secs(10)), if watcher.is_none() => {}
+            }
+        }
+    }
+
+    /// Checks each member on their own schedule: every `interval` while they're passing or
+    /// haven't failed enough to leave the ring, backing off after that, and right away when
+    /// their config changes.
+    #[allow(clippy::too_many_arguments)]
     pub async fn health_check_task(
+        config: Arc<RingConfig>,
         members: Arc<RwLock<Vec<Member>>>,
         health: Arc<RwLock<HashMap<String, Health>>>,
+        details: Arc<RwLock<HashMap<String, CheckDetails>>>,
+        uptime: Arc<RwLock<HashMap<String, Uptime>>>,
+        db: Arc<Mutex<SqliteConnection>>,
+        mut generation: watch::Receiver<u64>,
+        mut recheck_requests: UnboundedReceiver<(String, RecheckReply)>,
     ) {
-        let mut interval = interval(Duration::from_secs(60));
+        // Pages and the embed would otherwise all be reading the history, so uptime is only
+        // worked out here, once at startup and then after each round of checks.
+        Self::refresh_uptime(&db, &uptime).await;
+        // Wait for the first member list to load before checking anyone.
+        if generation
+            .wait_for(|generation| *generation > 0)
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let client = HealthClient::new(&config);
+        let matcher = EmbedMatcher::new(&config.embed_url());
+        let webhooks = Arc::new(Webhooks::new(&config, db.clone()));
+        let mut schedules: HashMap<String, Schedule> = HashMap::new();
+        let mut requested: Vec<(String, RecheckReply)> = vec![];
+        let mut waiting: HashMap<String, Vec<RecheckReply>> = HashMap::new();
+        // The last certificate state seen for each member, which checks that never got to see a
+        // certificate leave alone.
+        let mut certificates: HashMap<String, &'static str> = HashMap::new();
         let mut is_first = true;
 
         loop {
-            interval.tick().await;
+            // Members on a break or on their way out aren't checked, so they can't fail.
+            let today = config.order.today();
+            let checked: Vec<Member> = members
+                .read()
+                .await
+                .iter()
+                .filter(|m| {
+                    matches!(
+                        m.status.on(today),
+                        MemberStatus::Active | MemberStatus::Pending
+                    )
+                })
+                .cloned()
+                .collect();
 
-            if is_first {
-                println!("Performing first healthcheck...");
+            let now = Instant::now();
+            schedules.retain(|slug, _| checked.iter().any(|member| &member.slug == slug));
+            waiting.retain(|slug, _| schedules.contains_key(slug));
+            certificates.retain(|slug, _| schedules.contains_key(slug));
+            for member in checked {
+                match schedules.entry(member.slug.clone()) {
+                    Entry::Occupied(mut entry) => {
+                        let schedule = entry.get_mut();
+                        if schedule.member != member {
+                            *schedule = Schedule::new(member, now);
+                            schedule.fresh = true;
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(Schedule::new(member, now));
+                    }
+                }
             }
-            let new_members = read_members().await.expect("failed to read members");
-            let member_data: Vec<(String, String)> = new_members
-                .iter()
-                .map(|m| (m.slug.clone(), m.url.clone()))
+            client.retain(
+                &schedules
+                    .values()
+                    .map(|schedule| schedule.member.healthcheck_url())
+                    .collect::<Vec<&str>>(),
+            );
+            health
+                .write()
+                .await
+                .retain(|slug, _| schedules.contains_key(slug));
+            details
+                .write()
+                .await
+                .retain(|slug, _| schedules.contains_key(slug));
+            for (slug, reply) in requested.drain(..) {
+                match schedules.get_mut(&slug) {
+                    Some(schedule) => {
+                        schedule.due = now;
+                        schedule.fresh = true;
+                        waiting.entry(slug).or_default().push(reply);
+                    }
+                    None => {
+                        let _ = reply.send(None);
+                    }
+                }
+            }
+
+            let due: Vec<Member> = schedules
+                .values()
+                .filter(|schedule| schedule.due <= now)
+                .map(|schedule| schedule.member.clone())
                 .collect();
-            *members.write().await = new_members;
+            if !due.is_empty() {
+                if is_first {
+                    println!("Performing first healthcheck...");
+                }
+                let checked: Vec<((String, Health, Duration), CheckDetails)> = stream::iter(due)
+                    .map(async |member| {
+                        let (health, latency) = check_health_with_retries(
+                            &client,
+                            &matcher,
+                            &member,
+                            &config.healthcheck,
+                        )
+                        .await;
+                        let details = check_details(&client, &member, &config.healthcheck).await;
+                        ((member.slug, health, latency), details)
+                    })
+                    .buffer_unordered(config.healthcheck.concurrency)
+                    .collect()
+                    .await;
+                let (results, checked_details): (Vec<_>, Vec<_>) = checked.into_iter().unzip();
+                // History keeps every result, while the ring only follows settled ones.
+                Self::record_history(&db, &results).await;
+                Self::refresh_uptime(&db, &uptime).await;
 
-            *health.write().await = stream::iter(member_data)
-                .map(async |(slug, url)| {
-                    let health = check_health(&url, &slug).await;
-                    (slug, health)
-                })
-                .buffer_unordered(8)
-                .collect()
-                .await;
-            if is_first {
-                println!("First healthcheck completed!");
-                is_first = false;
+                let mut details = details.write().await;
+                let mut health = health.write().await;
+                let checked_at = Utc::now();
+                for ((slug, observed, latency), member_details) in
+                    results.into_iter().zip(checked_details)
+                {
+                    let schedule = match schedules.get_mut(&slug) {
+                        Some(schedule) => schedule,
+                        None => continue,
+                    };
+                    // A config change or a recheck might be the fix, so a pass counts straight
+                    // away. Failures still need a streak, or anyone could recheck a member out of
+                    // the ring during a blip.
+                    let previous = if schedule.fresh && matches!(observed, Health::Ok(_)) {
+                        None
+                    } else {
+                        health.get(&slug)
+                    };
+                    let settled =
+                        schedule
+                            .streak
+                            .settle(previous, observed.clone(), &config.healthcheck);
+                    schedule.fresh = false;
+                    schedule.due = Instant::now() + schedule.streak.delay(&config.healthcheck);
+                    for reply in waiting.remove(&slug).unwrap_or_default() {
+                        let mut member = schedule.member.clone();
+                        member.status = member.status.on(today);
+                        let _ = reply.send(Some(Recheck {
+                            slug: slug.clone(),
+                            name: schedule.member.name.clone(),
+                            url: schedule.member.healthcheck_url().to_string(),
+                            embed_kind: schedule.member.embed_kind,
+                            checked_at,
+                            latency_ms: latency.as_millis(),
+                            health: observed.clone(),
+                            embed_form: observed.embed_form(),
+                            ok: matches!(observed, Health::Ok(_)),
+                            reason: observed.to_string(),
+                            in_ring: Self::is_in_ring(&member, Some(&settled)),
+                            details: member_details.clone(),
+                        }));
+                    }
+                    // Members' first results aren't transitions, or every restart would be one.
+                    if let Some(old) = health.get(&slug) {
+                        if old.state() != settled.state() {
+                            webhooks.send(
+                                Transition {
+                                    event: "health",
+                                    ring: config.name.clone(),
+                                    slug: slug.clone(),
+                                    old_state: old.state(),
+                                    new_state: settled.state(),
+                                    reason: settled.to_string(),
+                                    timestamp: checked_at,
+                                },
+                                schedule.member.webhook.as_deref(),
+                            );
+                        }
+                    }
+                    // Unlike health, a certificate that's already expiring the first time it's
+                    // seen is news, since nobody has been told about it yet.
+                    if let (Some(new), Some(expires_at)) = (
+                        member_details.certificate_state(),
+                        member_details.certificate_expires_at,
+                    ) {
+                        let old = certificates.insert(slug.clone(), new);
+                        if old.map_or(new != "valid", |old| old != new) {
+                            webhooks.send(
+                                Transition {
+                                    event: "certificate",
+                                    ring: config.name.clone(),
+                                    slug: slug.clone(),
+                                    old_state: old.unwrap_or("unknown"),
+                                    new_state: new,
+                                    reason: format!(
+                                        "tls certificate {} on {}",
+                                        if new == "expired" {
+                                            "expired"
+                                        } else {
+                                            "expires"
+                                        },
+                                        expires_at.format("%Y-%m-%d")
+                                    ),
+                                    timestamp: checked_at,
+                                },
+                                schedule.member.webhook.as_deref(),
+                            );
+                        }
+                    }
+                    details.insert(slug.clone(), member_details);
+                    health.insert(slug, settled);
+                }
+                drop(health);
+                drop(details);
+                if is_first {
+                    println!("First healthcheck completed!");
+                    is_first = false;
+                }
+            }
+
+            // Wake up at least every interval anyway, since statuses change with the date.
+            let until_due = schedules
+                .values()
+                .map(|schedule| schedule.due.saturating_duration_since(Instant::now()))
+                .fold(config.healthcheck.interval(), Duration::min);
+            select! {
+                _ = sleep(until_due) => {}
+                changed = generation.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+                Some(request) = recheck_requests.recv() => requested.push(request),
+            }
+        }
+    }
+
+    /// Appends a round of healthcheck results to the history and drops anything too old to
+    /// matter. Failing to write history is logged but doesn't stop healthchecks.
+    async fn record_history(db: &Mutex<SqliteConnection>, results: &[(String, Health, Duration)]) {
+        let timestamp = Utc::now();
+        let rows: Vec<NewHealthCheck> = results
+            .iter()
+            .map(|(slug, health, latency)| NewHealthCheck {
+                slug,
+                timestamp,
+                ok: matches!(health, Health::Ok(_)),
+                failure: health.failure_kind(),
+                latency_ms: latency.as_millis().try_into().unwrap_or(i32::MAX),
+            })
+            .collect();
+
+        let mut db = db.lock().await;
+        let result = diesel::insert_into(health_checks::table)
+            .values(&rows)
+            .execute(&mut *db)
+            .and_then(|_| Uptime::prune(&mut db));
+        if let Err(err) = result {
+            eprintln!("Couldn't record healthcheck history: {}", err);
+        }
+    }
+
+    async fn refresh_uptime(
+        db: &Mutex<SqliteConnection>,
+        uptime: &RwLock<HashMap<String, Uptime>>,
+    ) {
+        let fetched = Uptime::fetch(&mut *db.lock().await);
+        match fetched {
+            Ok(fetched) => {
+                *uptime.write().await = fetched
+                    .into_iter()
+                    .map(|uptime| (uptime.slug.clone(), uptime))
+                    .collect()
             }
+            Err(err) => eprintln!("Couldn't read healthcheck history: {}", err),
         }
     }
 }
 
 impl Drop for MemberManager {
     fn drop(&mut self) {
+        self.watch_handle.abort();
         self.interval_handle.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_health_like_before() {
+        let json = |health: Health| serde_json::to_string(&health).unwrap();
+        assert_eq!(json(Health::Ok(EmbedForm::Script)), "\"Ok\"");
+        assert_eq!(json(Health::Timeout), "\"Timeout\"");
+        assert_eq!(json(Health::HttpError(404)), "{\"HttpError\":404}");
+        assert_eq!(
+            json(Health::SlugMismatch("someone".to_string())),
+            "{\"SlugMismatch\":\"someone\"}"
+        );
+        assert_eq!(
+            json(Health::EmbedHidden(EmbedForm::Iframe)),
+            "{\"EmbedHidden\":\"iframe\"}"
+        );
+    }
+
+    const OK: Health = Health::Ok(EmbedForm::Iframe);
+
+    /// Settles each of `observed` in turn, starting from `previous`, and returns whether the
+    /// member was in the ring after each one.
+    fn in_ring_after(previous: Option<Health>, observed: &[Health]) -> Vec<bool> {
+        let config = HealthcheckConfig::default();
+        let mut streak = Streak::default();
+        let mut health = previous;
+        observed
+            .iter()
+            .map(|observed| {
+                let settled = streak.settle(health.as_ref(), observed.clone(), &config);
+                let in_ring = matches!(settled, Health::Ok(_));
+                health = Some(settled);
+                in_ring
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drops_members_after_enough_failures() {
+        // `failures_to_drop` is 3 by default.
+        assert_eq!(
+            in_ring_after(
+                Some(OK),
+                &[Health::Timeout, Health::Timeout, Health::Timeout]
+            ),
+            [true, true, false]
+        );
+        // A pass in between starts the count over.
+        assert_eq!(
+            in_ring_after(
+                Some(OK),
+                &[
+                    Health::Timeout,
+                    Health::Timeout,
+                    OK,
+                    Health::Timeout,
+                    Health::Timeout
+                ]
+            ),
+            [true, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn returns_members_after_enough_passes() {
+        // `successes_to_return` is 2 by default.
+        assert_eq!(
+            in_ring_after(Some(Health::DnsFailure), &[OK, OK]),
+            [false, true]
+        );
+        assert_eq!(
+            in_ring_after(Some(Health::DnsFailure), &[OK, Health::Timeout, OK, OK]),
+            [false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn counts_first_checks_straight_away() {
+        assert_eq!(in_ring_after(None, &[OK]), [true]);
+        assert_eq!(in_ring_after(None, &[Health::Timeout]), [false]);
+    }
+
+    #[test]
+    fn backs_off_between_interval_and_max_backoff() {
+        let config = HealthcheckConfig::default();
+        let delay = |failed| Streak { passed: 0, failed }.delay(&config);
+        let within = |delay: Duration, base: Duration| delay >= base && delay <= base.mul_f64(1.1);
+
+        assert!(within(delay(0), config.interval()));
+        assert!(within(delay(config.failures_to_drop), config.interval()));
+        assert!(within(
+            delay(config.failures_to_drop + 2),
+            config.interval() * 4
+        ));
+        assert!(within(delay(u32::MAX), config.max_backoff()));
+        for failed in 0..64 {
+            assert!(delay(failed) >= config.interval());
+            assert!(delay(failed) <= config.max_backoff().mul_f64(1.1));
+        }
+    }
+}
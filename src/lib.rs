@@ -1,4 +1,12 @@
 pub mod config;
+pub mod csp;
+pub mod css;
+pub mod embed;
 pub mod health;
 pub mod models;
+pub mod order;
+pub mod parking;
+pub mod ring;
 pub mod schema;
+pub mod validate;
+pub mod webhook;
@@ -1,28 +1,37 @@
 #[macro_use]
 extern crate rocket;
-use chrono::{Datelike, Duration, Local, Utc};
-use diesel::{Connection, RunQueryDsl, SqliteConnection};
-use lazy_static::lazy_static;
-use overengineering::config::Member;
-use overengineering::health::{Health, MemberManager};
-use overengineering::models::{NewHit, SiteStats};
+use chrono::{Datelike, Duration, Local, NaiveDate, Utc};
+use diesel::RunQueryDsl;
+use overengineering::config::{Member, MemberColors, MemberStatus};
+use overengineering::css::{Color, Length};
+use overengineering::health::{CheckDetails, Health, MemberManager, RecheckError};
+use overengineering::models::{NewHit, SiteStats, Uptime};
+use overengineering::order::Rotation;
+use overengineering::ring::{Ring, RingConfig, Rings};
 use overengineering::schema::hits;
+use overengineering::validate::validate_members;
+use overengineering::webhook::member_key;
 use rand::seq::SliceRandom;
-use rocket::http::{Method, Status};
+use rocket::http::{Accept, Method, Status};
 use rocket::request::{FromRequest, Outcome, Request};
-use rocket::response::content::{RawHtml, RawJson};
+use rocket::response::content::{RawHtml, RawJavaScript, RawJson};
 use rocket::response::Redirect;
 use rocket::shield::Shield;
-use rocket::tokio::sync::Mutex;
+use rocket::{Build, Rocket};
 use rocket_cors::{AllowedHeaders, AllowedOrigins, CorsOptions};
+use serde_json::json;
 use sha2::{Digest, Sha256};
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::{convert::Infallible, future::Future, pin::Pin};
 
-lazy_static! {
-    static ref MEMBER_MANAGER: MemberManager = MemberManager::new();
-    static ref DB: Mutex<SqliteConnection> =
-        Mutex::new(SqliteConnection::establish("./stats.db").unwrap());
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
 }
 
 fn html(mut markup: String) -> RawHtml<String> {
@@ -59,19 +68,24 @@ impl<'r> FromRequest<'r> for LastSegment {
 }
 
 #[get("/")]
-async fn index() -> RawHtml<String> {
+async fn index(ring: &Ring) -> RawHtml<String> {
+    let config = ring.config();
     let mut ok_members: Vec<Member> = vec![];
     let mut not_ok_members: Vec<(Member, Option<Health>)> = vec![];
+    let mut hiatus_members: Vec<(Member, Option<NaiveDate>)> = vec![];
 
-    for (member, health) in MEMBER_MANAGER.members().await {
-        if member.is_being_removed {
+    let uptime = ring.members.uptime().await;
+    let details = ring.members.details().await;
+    for (member, health) in ring.members.members().await {
+        if MemberManager::is_in_ring(&member, health.as_ref()) {
+            ok_members.push(member);
             continue;
         }
 
-        if matches!(health, Some(Health::Ok)) {
-            ok_members.push(member);
-        } else {
-            not_ok_members.push((member, health));
+        match member.status {
+            MemberStatus::Active => not_ok_members.push((member, health)),
+            MemberStatus::Hiatus { until } => hiatus_members.push((member, until)),
+            MemberStatus::Pending | MemberStatus::Removed => {}
         }
     }
 
@@ -82,7 +96,7 @@ async fn index() -> RawHtml<String> {
                 <head>
                     <meta charset='utf-8'>
                     <meta name='viewport' content='width=device-width, initial-scale=1.0'>
-                    <title>overengineeRING</title>
+                    <title>{ring_name}</title>
                     <style>
                         body {{
                             background: #0b1728;
@@ -112,6 +126,15 @@ async fn index() -> RawHtml<String> {
                         p, ul, ol {{ margin: 10px 0; }}
                         a {{ color: #ff6b60; }}
                         .dim {{ color: #4a6294; }}
+                        .avatar {{
+                            width: 1.25em;
+                            height: 1.25em;
+                            display: inline-block;
+                            margin: 0 6px 0 0;
+                            vertical-align: text-bottom;
+                            border-radius: 2px;
+                        }}
+                        .aside {{ margin-left: 6px; }}
                         .failures {{ font-size: 0.9em; opacity: 0.5; }}
                         table {{
                             border-collapse: collapse;
@@ -141,40 +164,35 @@ async fn index() -> RawHtml<String> {
                 </head>
                 <body>
                     <h1>
-                        <img src='https://raw.githubusercontent.com/kognise/overengineering/main/banner.png' alt='overengineeRING logo' title='overengineeRING' width='1200' height='200'>
+                        <img src='{banner}' alt='{ring_name} logo' title='{ring_name}' width='1200' height='200'>
                     </h1>
 
                     <main>
-                        <p class='banner'>overengineeRING 2: now with healthchecks, cooler people, and seasonal themes!</p>
+                        <p class='banner'>{tagline}</p>
 
-                        <p>a <a href='https://en.wikipedia.org/wiki/Webring' target='_blank' rel='noopener noreferrer'>webring</a> of interesting people; makers of technology, music, art, or writing. (<a href='https://github.com/kognise/overengineering/' target='_blank' rel='noopener noreferrer'>github</a>)</p>
-                        <p>everyone on this list has different skill levels and different personalities, but i guarantee you'll get something out of talking to them or looking at their sites.</p>
+                        {description}
 
                         <h2>alive members</h2>
-                        <p>the ring order is randomized and changes daily! current ordering:</p>
+                        <p>{order_description}</p>
                         <ol>{ok_member_list}</ol>
+                        {hiatus_section}
 
                         <h2>criteria</h2>
-                        <ul>
-                            <li>this is a webring containing personal sites only.</li>
-                            <li>you should be an interesting person! a great gauge is whether you think people will get something out of visiting your website, whether inspiration or curiosity.</li>
-                            <li>no illegal, nsfw, or gory content is allowed. duh.</li>
-                            <li>members must embed the webring widget on the homepage of their site.</li>
-                            <li>don't be evil, unless you really have to.</li>
-                            <li>if you're part of multiple webrings, this should be the most prominent. (i know some other webrings don't do this but people find it really confusing otherwise, sorry :<)</li>
-                        </ul>
-                        <p>do you make things and have a website showcasing such things? you should join! email <a href='mailto:hi@kognise.dev' target='_blank' rel='noopener noreferrer'>hi@kognise.dev</a> asking politely, or directly <a href='https://github.com/kognise/overengineering/new/main?filename=members/your_name_here.yaml&value=%23%20make%20sure%20to%20change%20the%20filename%20to%20your%5Fname%2Eyaml%20%28alphanumeric%20with%20underscores%29%0A%23%20and%20delete%20the%20comments%21%0A%23%0A%23%20excited%20to%20have%20you%20join%20overengineeRING%20%3A%29%0A%0Aname%3A%20your%20name%20here%0Aurl%3A%20https%3A%2F%2Fexample%2Ecom%2F%0A%0A%23%20%3D%3D%3D%3D%20optional%20settings%3A%20%3D%3D%3D%3D%0A%23%20colors%3A%0A%23%20%20%20border%3A%20%27%23000000%27%0A%23%20%20%20text%3A%20%27%23000000%27%0A%23%20%20%20links%3A%20%27%230000ee%27%0A%23%20%20%20on%5Flinks%3A%20%27%23ffffff%27%0A%23%20stylesheets%3A%0A%23%20%20%20%2D%20https%3A%2F%2Ffonts%2Egoogleapis%2Ecom%2Fcss2%3Ffamily%3DIBM%2BPlex%2BMono%3Awght%40400%26display%3Dswap%0A%23%20font%5Fstack%3A%20%27%22IBM%20Plex%20Mono%22%2C%20monospace%27%0A%23%20font%5Fsize%3A%201%2E2em' target='_blank' rel='noopener noreferrer'>create a pull request</a> adding your config file.
+                        <ul>{criteria}</ul>
+                        <p>do you make things and have a website showcasing such things? you should join! email <a href='mailto:{contact_email}' target='_blank' rel='noopener noreferrer'>{contact_email}</a> asking politely, or directly <a href='{repository}/new/main?filename=members/your_name_here.yaml&value=%23%20make%20sure%20to%20change%20the%20filename%20to%20your%5Fname%2Eyaml%20%28alphanumeric%20with%20underscores%29%0A%23%20and%20delete%20the%20comments%21%0A%23%0A%23%20excited%20to%20have%20you%20join%20overengineeRING%20%3A%29%0A%0Aname%3A%20your%20name%20here%0Aurl%3A%20https%3A%2F%2Fexample%2Ecom%2F%0A%0A%23%20%3D%3D%3D%3D%20optional%20settings%3A%20%3D%3D%3D%3D%0A%23%20colors%3A%0A%23%20%20%20border%3A%20%27%23000000%27%0A%23%20%20%20text%3A%20%27%23000000%27%0A%23%20%20%20links%3A%20%27%230000ee%27%0A%23%20%20%20on%5Flinks%3A%20%27%23ffffff%27%0A%23%20stylesheets%3A%0A%23%20%20%20%2D%20https%3A%2F%2Ffonts%2Egoogleapis%2Ecom%2Fcss2%3Ffamily%3DIBM%2BPlex%2BMono%3Awght%40400%26display%3Dswap%0A%23%20font%5Fstack%3A%20%27%22IBM%20Plex%20Mono%22%2C%20monospace%27%0A%23%20font%5Fsize%3A%201%2E2em' target='_blank' rel='noopener noreferrer'>create a pull request</a> adding your config file.
                         
                         <div class='failures'>
                             <h2>healthcheck failures</h2>
                             <p>members who fail their healthchecks will not show up on webring member sites or the random button.</p>
-                            <p>(the plurality of dead members is partially caused by some serious downtime over the past couple of years. v2 fixes this!)</p>
+                            {failures_note}
                             <table>
                                 <thead>
                                     <tr>
                                         <th>name</th>
                                         <th>failure reason</th>
+                                        <th>uptime 7d / 30d / 90d</th>
                                         <th>url</th>
+                                        <th></th>
                                     </tr>
                                 </thead>
                                 <tbody>
@@ -189,40 +207,140 @@ async fn index() -> RawHtml<String> {
         ok_member_list = ok_members.into_iter()
             .map(|member| format!("
                 <li>
-                    <div><a href='{url}' target='_blank' rel='noopener noreferrer'>{name}</a></div>
-                    <div class='dim'>{url}</div>
-                </li>", url = member.url, name = member.name))
+                    <div>
+                        {avatar}<a href='{url}' target='_blank' rel='noopener noreferrer'>{name}</a>{pronouns}{feed}
+                    </div>
+                    {description}
+                    <div class='dim'>{url}{tags}</div>
+                    <div class='dim'>checked: {embed_kind} on {healthcheck_url} (<a href='{status_url}'>status</a>)</div>
+                    {warnings}
+                </li>",
+                warnings = warnings_fmt(details.get(&member.slug)),
+                status_url = ring.config().path(&format!("/health/{}", member.slug)),
+                embed_kind = member.embed_kind,
+                healthcheck_url = escape_html(member.healthcheck_url()),
+                url = member.url,
+                name = escape_html(&member.name),
+                avatar = member.avatar
+                    .map(|avatar| format!("<img class='avatar' src='{}' alt='' loading='lazy'>", escape_html(&avatar)))
+                    .unwrap_or_default(),
+                pronouns = member.pronouns
+                    .map(|pronouns| format!("<span class='dim aside'>({})</span>", escape_html(&pronouns)))
+                    .unwrap_or_default(),
+                feed = member.feed
+                    .map(|feed| format!("<a class='aside' href='{}' target='_blank' rel='noopener noreferrer'>feed</a>", escape_html(&feed)))
+                    .unwrap_or_default(),
+                description = member.description
+                    .map(|description| format!("<div>{}</div>", escape_html(&description)))
+                    .unwrap_or_default(),
+                tags = member.tags.iter()
+                    .map(|tag| format!(" #{}", tag))
+                    .collect::<String>(),
+            ))
             .collect::<Vec<String>>()
             .join(""),
+        ring_name = config.name,
+        banner = config.banner,
+        tagline = config.tagline,
+        description = config.description.iter()
+            .map(|paragraph| format!("<p>{}</p>", paragraph))
+            .collect::<String>(),
+        criteria = config.criteria.iter()
+            .map(|criterion| format!("<li>{}</li>", criterion))
+            .collect::<String>(),
+        contact_email = config.contact_email,
+        repository = config.repository,
+        failures_note = config.failures_note.as_ref()
+            .map(|note| format!("<p>{}</p>", note))
+            .unwrap_or_default(),
+        order_description = match ring.members.order().rotation {
+            Rotation::Daily => "the ring order is randomized and changes daily! current ordering:",
+            Rotation::Weekly => "the ring order is randomized and changes weekly! current ordering:",
+            Rotation::Fixed => "members are listed in the order they joined:",
+        },
+        hiatus_section = if hiatus_members.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<h2>on hiatus</h2><p>taking a break, and keeping their spot for when they're back.</p><ul>{}</ul>",
+                hiatus_members.into_iter()
+                    .map(|(member, until)| format!(
+                        "<li><a href='{url}' target='_blank' rel='noopener noreferrer'>{name}</a>{until}</li>",
+                        url = member.url,
+                        name = escape_html(&member.name),
+                        until = until
+                            .map(|until| format!("<span class='dim aside'>back {}</span>", until))
+                            .unwrap_or_default(),
+                    ))
+                    .collect::<String>(),
+            )
+        },
         not_ok_member_list = not_ok_members.into_iter()
             .map(|(member, health)| format!(
-                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
-                member.name,
-                match health {
-                    Some(Health::Ok) => unreachable!(),
-                    Some(Health::SiteUnreachable) => "site unreachable",
-                    Some(Health::NoWebringEmbed) => "embed missing from site",
-                    Some(Health::SlugMismatch(_)) => "embed url has wrong slug",
-                    None => "healthcheck pending...",
+                "<tr><td><a href='{}'>{}</a></td><td>{}</td><td>{}</td><td>{}</td><td><form method='post' action='{}'><button>recheck</button></form></td></tr>",
+                ring.config().path(&format!("/health/{}", member.slug)),
+                escape_html(&member.name),
+                match &health {
+                    Some(health) => escape_html(&health.to_string()),
+                    None => "healthcheck pending...".to_string(),
+                } + &warnings_fmt(details.get(&member.slug)),
+                uptime_fmt(uptime.get(&member.slug)),
+                match health.as_ref().and_then(Health::destination) {
+                    Some(destination) => format!(
+                        "{}<div>now goes to <a href='{destination}' target='_blank' rel='noopener noreferrer'>{destination}</a></div>",
+                        escape_html(member.healthcheck_url()),
+                        destination = escape_html(destination),
+                    ),
+                    None => escape_html(member.healthcheck_url()),
                 },
-                member.url,
+                ring.config().path(&format!("/health/{}/recheck", member.slug)),
             ))
             .collect::<Vec<String>>()
             .join("")
     ))
 }
 
+fn warnings_fmt(details: Option<&CheckDetails>) -> String {
+    details
+        .into_iter()
+        .flat_map(|details| &details.warnings)
+        .map(|warning| {
+            format!(
+                "<div class='dim'>warning: {}</div>",
+                escape_html(&warning.to_string())
+            )
+        })
+        .collect()
+}
+
+fn uptime_fmt(uptime: Option<&Uptime>) -> String {
+    let percent = |value: Option<f64>| match value {
+        Some(value) => format!("{:.1}%", value),
+        None => "-".to_string(),
+    };
+    match uptime {
+        Some(uptime) => format!(
+            "{} / {} / {}",
+            percent(uptime.days_7),
+            percent(uptime.days_30),
+            percent(uptime.days_90)
+        ),
+        None => "-".to_string(),
+    }
+}
+
 #[get("/rand")]
-async fn random(last_segment: LastSegment) -> Redirect {
+async fn random(ring: &Ring, last_segment: LastSegment) -> Redirect {
     Redirect::to(
-        MEMBER_MANAGER
-            .members()
+        ring.members
+            .ring()
             .await
             .into_iter()
-            .filter_map(|(member, health)| {
-                if matches!(health, Some(Health::Ok))
-                    && last_segment.0 != Some(member.slug)
-                    && !member.is_being_removed
+            .filter_map(|member| {
+                if !last_segment
+                    .0
+                    .as_deref()
+                    .is_some_and(|slug| member.answers_to(slug))
                 {
                     Some(member.url)
                 } else {
@@ -236,25 +354,77 @@ async fn random(last_segment: LastSegment) -> Redirect {
     )
 }
 
-#[get("/embed/<slug>?<text_color>&<border_color>&<link_color>&<on_link_color>&<font_size>")]
+/// Query parameters that override a member's configured embed styling. Values that don't parse
+/// are treated as missing.
+#[derive(FromForm)]
+struct EmbedOverrides {
+    text_color: Option<Color>,
+    border_color: Option<Color>,
+    link_color: Option<Color>,
+    on_link_color: Option<Color>,
+    text_color_dark: Option<Color>,
+    border_color_dark: Option<Color>,
+    link_color_dark: Option<Color>,
+    on_link_color_dark: Option<Color>,
+    font_size: Option<Length>,
+}
+
+impl EmbedOverrides {
+    fn colors(&self, member: &Member) -> MemberColors {
+        let colors = &member.colors;
+        MemberColors {
+            text: self.text_color.clone().unwrap_or(colors.text.clone()),
+            border: self.border_color.clone().unwrap_or(colors.border.clone()),
+            links: self.link_color.clone().unwrap_or(colors.links.clone()),
+            on_links: self
+                .on_link_color
+                .clone()
+                .unwrap_or(colors.on_links.clone()),
+        }
+    }
+
+    /// The dark palette, if either the member or the query asks for one. Anything not given falls
+    /// back to the member's dark colors and then to the light ones.
+    fn dark_colors(&self, member: &Member, light: &MemberColors) -> Option<MemberColors> {
+        let has_overrides = self.text_color_dark.is_some()
+            || self.border_color_dark.is_some()
+            || self.link_color_dark.is_some()
+            || self.on_link_color_dark.is_some();
+        if member.colors_dark.is_none() && !has_overrides {
+            return None;
+        }
+
+        let colors = member.colors_dark.as_ref().unwrap_or(light);
+        Some(MemberColors {
+            text: self.text_color_dark.clone().unwrap_or(colors.text.clone()),
+            border: self
+                .border_color_dark
+                .clone()
+                .unwrap_or(colors.border.clone()),
+            links: self.link_color_dark.clone().unwrap_or(colors.links.clone()),
+            on_links: self
+                .on_link_color_dark
+                .clone()
+                .unwrap_or(colors.on_links.clone()),
+        })
+    }
+}
+
+#[get("/embed/<slug>?<overrides..>")]
 async fn embed(
+    ring: &Ring,
     ip: IpAddr,
     slug: &str,
-    text_color: Option<&str>,
-    border_color: Option<&str>,
-    link_color: Option<&str>,
-    on_link_color: Option<&str>,
-    font_size: Option<&str>,
+    overrides: EmbedOverrides,
 ) -> Result<RawHtml<String>, Status> {
     // Healthy members, and this site!
-    let members: Vec<Member> = MEMBER_MANAGER
+    let members: Vec<Member> = ring
+        .members
         .members()
         .await
         .into_iter()
         .filter_map(|(member, health)| {
-            if (matches!(health, Some(Health::Ok)) && !member.is_being_removed)
-                || member.slug == slug
-            {
+            if MemberManager::is_in_ring(&member, health.as_ref()) || member.answers_to(slug) {
                 Some(member)
             } else {
                 None
@@ -265,7 +435,7 @@ async fn embed(
     let (member_index, member) = match members
         .iter()
         .enumerate()
-        .find(|(_, site)| site.slug == slug)
+        .find(|(_, site)| site.answers_to(slug))
     {
         Some((idx, m)) => (idx, m),
         None => {
@@ -280,18 +450,40 @@ async fn embed(
         };
         let ip_hash = Sha256::digest(ip_octets);
         let ip_hash = ip_hash.as_slice();
+        // Hits are recorded under the current slug even when the embed uses an alias.
         let new_hit = NewHit {
-            slug: &slug,
+            slug: &member.slug,
             timestamp: Utc::now(),
             ip_hash,
         };
-        let mut db = DB.lock().await;
-        diesel::insert_into(hits::table)
+        let mut db = ring.db.lock().await;
+        // Losing a hit only skews stats, so the embed is served anyway.
+        if let Err(err) = diesel::insert_into(hits::table)
             .values(&new_hit)
             .execute(&mut *db)
-            .unwrap();
+        {
+            eprintln!("Couldn't record hit: {}", err);
+        }
     }
 
+    let colors = overrides.colors(member);
+    let dark_style = match overrides.dark_colors(member, &colors) {
+        Some(dark) => format!(
+            "
+                @media (prefers-color-scheme: dark) {{
+                    body {{ color: {text_color}; border-color: {border_color}; }}
+                    a {{ color: {link_color}; }}
+                    a:hover {{ color: {on_link_color}; background: {link_color}; }}
+                }}
+            ",
+            text_color = dark.text,
+            border_color = dark.border,
+            link_color = dark.links,
+            on_link_color = dark.on_links,
+        ),
+        None => String::new(),
+    };
+
     Ok(html(format!(
         "
             <!DOCTYPE html>
@@ -299,7 +491,7 @@ async fn embed(
                 <head>
                     <meta charset='utf-8'>
                     <meta name='robots' content='noindex'>
-                    <title>overengineeRING embed</title>
+                    <title>{ring_name} embed</title>
                     <style>
                         body {{
                             font-family: {font_stack};
@@ -323,16 +515,17 @@ async fn embed(
                         a::before {{ content: '['; }}
                         a::after {{ content: ']'; }}
                         p {{ margin: 0 0 10px 0; }}
+                        {dark_style}
                     </style>
                     {head_include}
                 </head>
                 <body>
                     <a href='{prev_url}' target='_parent'>&laquo; prev</a>
                     <nav>
-                        <p>{name} @ overengineeRING</p>
+                        <p>{name} @ {ring_name}</p>
                         <div>
-                            <a href='/' target='_blank'>list</a>
-                            <a href='/rand' target='_parent'>rand</a>
+                            <a href='{list_url}' target='_blank'>list</a>
+                            <a href='{rand_url}' target='_parent'>rand</a>
                         </div>
                     </nav>
                     <a href='{next_url}' target='_parent'>next &raquo;</a>
@@ -342,23 +535,58 @@ async fn embed(
                 </body>
             </html>
         ",
-        name = member.name,
+        name = escape_html(&member.name),
+        ring_name = ring.config().name,
+        list_url = ring.config().path("/"),
+        rand_url = ring.config().path("/rand"),
         prev_url = members[if member_index == 0 { members.len() - 1 } else { member_index - 1 }].url,
         next_url = members[(member_index + 1) % members.len()].url,
-        font_stack = member.font_stack.as_ref().unwrap_or(&"monospace".to_string()),
-        font_size = font_size.unwrap_or(member.font_size.as_ref().unwrap_or(&"initial".to_string())),
+        font_stack = member.font_stack.as_ref().map(ToString::to_string).unwrap_or("monospace".to_string()),
+        font_size = overrides.font_size.or(member.font_size).unwrap_or(Length::Keyword("initial")),
         head_include = member.stylesheets.iter()
             .map(|stylesheet| format!("<link rel='stylesheet' href='{}'>", stylesheet))
             .collect::<Vec<String>>()
             .join(""),
-        text_color = text_color.as_ref().unwrap_or(&member.colors.text.as_str()),
-        border_color = border_color.as_ref().unwrap_or(&member.colors.border.as_str()),
-        link_color = link_color.as_ref().unwrap_or(&member.colors.links.as_str()),
-        on_link_color = on_link_color.as_ref().unwrap_or(&member.colors.on_links.as_str()),
+        text_color = colors.text,
+        border_color = colors.border,
+        link_color = colors.links,
+        on_link_color = colors.on_links,
         theme_js = get_theme_js(),
     )))
 }
 
+/// For sites that would rather paste a script tag than an iframe: adds the embed right after the
+/// script, passing along any query parameters.
+#[get("/embed/<slug>/widget.js")]
+async fn widget(ring: &Ring, slug: &str) -> Result<RawJavaScript<String>, Status> {
+    let is_member = ring
+        .members
+        .members()
+        .await
+        .iter()
+        .any(|(member, _)| member.answers_to(slug));
+    if !is_member {
+        return Err(Status::NotFound);
+    }
+
+    Ok(RawJavaScript(format!(
+        "
+            (function () {{
+                var script = document.currentScript;
+                var iframe = document.createElement('iframe');
+                iframe.src = script.src.replace(/\\/widget\\.js(?=\\?|#|$)/, '');
+                iframe.title = {title};
+                iframe.width = '100%';
+                iframe.height = '100';
+                iframe.frameBorder = '0';
+                iframe.style.userSelect = 'none';
+                script.parentNode.insertBefore(iframe, script.nextSibling);
+            }})();
+        ",
+        title = serde_json::to_string(&format!("{} embed", ring.config().name)).unwrap(),
+    )))
+}
+
 fn get_theme_js() -> &'static str {
     let pacific_time_ish = Local::now() - Duration::hours(8);
     let month = pacific_time_ish.month();
@@ -382,22 +610,21 @@ fn num_fmt(num: i32) -> String {
 }
 
 #[get("/stats")]
-async fn stats() -> RawHtml<String> {
-    let members: Vec<Member> = MEMBER_MANAGER
-        .members()
-        .await
-        .into_iter()
-        .filter_map(|(member, health)| {
-            if matches!(health, Some(Health::Ok)) && !member.is_being_removed {
-                Some(member)
-            } else {
-                None
-            }
-        })
-        .collect();
+async fn stats(ring: &Ring) -> RawHtml<String> {
+    let members = ring.members.ring().await;
     let stats = {
-        let mut db = DB.lock().await;
-        SiteStats::fetch(&mut db).unwrap()
+        let all_members = ring.members.members().await;
+        let aliases: Vec<(&str, &str)> = all_members
+            .iter()
+            .flat_map(|(member, _)| {
+                member
+                    .aliases
+                    .iter()
+                    .map(move |alias| (alias.as_str(), member.slug.as_str()))
+            })
+            .collect();
+        let mut db = ring.db.lock().await;
+        SiteStats::fetch(&mut db, &aliases).unwrap()
     };
 
     let mut visitors_sum = 0;
@@ -420,7 +647,7 @@ async fn stats() -> RawHtml<String> {
                 <head>
                     <meta charset='utf-8'>
                     <meta name='viewport' content='width=device-width, initial-scale=1.0'>
-                    <title>overengineeRING</title>
+                    <title>{ring_name} stats</title>
                     <style>
                         body {{
                             background: #0b1728;
@@ -461,8 +688,8 @@ async fn stats() -> RawHtml<String> {
                 </head>
                 <body>
                     <h1>stats (beta)</h1>
-                    <p><a href='/'>go home</a></p>
-                    <p>overengineeRING drove {driven_pct}% of the new visitors to members' websites!</p>
+                    <p><a href='{home_url}'>go home</a></p>
+                    <p>{ring_name} drove {driven_pct}% of the new visitors to members' websites!</p>
                     <table>
                         <thead>
                             <tr>
@@ -506,18 +733,295 @@ async fn stats() -> RawHtml<String> {
             })
             .collect::<Vec<String>>()
             .join(""),
+        ring_name = ring.config().name,
+        home_url = ring.config().path("/"),
         driven_pct = ((from_others_sum as f32 / visitors_sum as f32) * 100.0).round() as u32,
     ))
 }
 
+/// Every member as `[member, health, uptime, details, embed_form]`. New fields only ever go on the
+/// end, so clients reading by position keep working.
 #[get("/members.json")]
-async fn members() -> RawJson<String> {
-    RawJson(serde_json::to_string(&MEMBER_MANAGER.members().await).unwrap())
+async fn members(ring: &Ring) -> RawJson<String> {
+    let mut uptime = ring.members.uptime().await;
+    let mut details = ring.members.details().await;
+    let members: Vec<_> = ring
+        .members
+        .members()
+        .await
+        .into_iter()
+        .map(|(member, health)| {
+            let uptime = uptime.remove(&member.slug);
+            let details = details.remove(&member.slug).unwrap_or_default();
+            let embed_form = health.as_ref().and_then(Health::embed_form);
+            (member, health, uptime, details, embed_form)
+        })
+        .collect();
+    RawJson(serde_json::to_string(&members).unwrap())
+}
+
+/// The ring order on a given day (today by default), for checking what the ring will look like
+/// or did look like. Health is only known for the present, so this lists every active member.
+#[get("/order.json?<date>")]
+async fn order(ring: &Ring, date: Option<&str>) -> Result<RawJson<String>, Status> {
+    let order = ring.members.order();
+    let date = match date {
+        Some(date) => date.parse::<NaiveDate>().map_err(|_| Status::BadRequest)?,
+        None => order.today(),
+    };
+
+    let members: Vec<serde_json::Value> = ring
+        .members
+        .members_on(date)
+        .await
+        .into_iter()
+        .filter(|(member, _)| member.status == MemberStatus::Active)
+        .map(|(member, _)| json!({ "slug": member.slug, "name": member.name, "url": member.url }))
+        .collect();
+
+    Ok(RawJson(
+        json!({
+            "date": date,
+            "period": order.period(date),
+            "rotation": order.rotation,
+            "timezone": order.timezone.name(),
+            "members": members,
+        })
+        .to_string(),
+    ))
 }
 
-#[launch]
-async fn rocket() -> _ {
-    let _ = MEMBER_MANAGER.members().await;
+#[derive(Responder)]
+enum RecheckResponse {
+    Json(RawJson<String>),
+    Html(RawHtml<String>),
+}
+
+/// Checks a member right away, for when they've just fixed their embed. Answers with JSON when
+/// that's what the client prefers, and otherwise with a page for the recheck buttons on `/`.
+#[post("/health/<slug>/recheck")]
+async fn recheck(ring: &Ring, slug: &str, accept: Option<&Accept>) -> (Status, RecheckResponse) {
+    let result = ring.members.recheck(slug).await;
+    let (status, error) = match &result {
+        Ok(_) => (Status::Ok, None),
+        Err(RecheckError::UnknownMember) => (
+            Status::NotFound,
+            Some("no member goes by that slug".to_string()),
+        ),
+        Err(RecheckError::NotChecked) => (
+            Status::Conflict,
+            Some("members on hiatus or being removed aren't checked".to_string()),
+        ),
+        Err(RecheckError::TooSoon(wait)) => (
+            Status::TooManyRequests,
+            Some(format!(
+                "rechecked too recently, try again in {} seconds",
+                wait.as_secs() + 1
+            )),
+        ),
+    };
+
+    if accept.is_some_and(|accept| accept.preferred().media_type().is_json()) {
+        let body = match (&result, error) {
+            (Ok(recheck), _) => serde_json::to_string(recheck).unwrap(),
+            (Err(RecheckError::TooSoon(wait)), Some(error)) => {
+                json!({ "error": error, "retry_after": wait.as_secs() + 1 }).to_string()
+            }
+            (_, error) => json!({ "error": error }).to_string(),
+        };
+        return (status, RecheckResponse::Json(RawJson(body)));
+    }
+
+    let summary = match (&result, error) {
+        (Ok(recheck), _) => format!(
+            "
+                <h1>{name} {verdict}</h1>
+                <p>{reason}</p>
+                <p>{in_ring}</p>
+                <p class='dim'>checked {embed_kind} on {url} in {latency_ms}ms at {checked_at}</p>
+                {warnings}
+            ",
+            warnings = warnings_fmt(Some(&recheck.details)),
+            name = escape_html(&recheck.name),
+            verdict = if recheck.ok {
+                "passed their healthcheck"
+            } else {
+                "failed their healthcheck"
+            },
+            reason = escape_html(&recheck.reason),
+            in_ring = match (recheck.in_ring, recheck.ok) {
+                (true, true) => "in the ring",
+                (true, false) => "still in the ring until enough checks in a row fail",
+                (false, _) => "not in the ring",
+            },
+            embed_kind = recheck.embed_kind,
+            url = escape_html(&recheck.url),
+            latency_ms = recheck.latency_ms,
+            checked_at = recheck.checked_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        ),
+        (_, error) => format!(
+            "<h1>couldn't recheck {}</h1><p>{}</p>",
+            escape_html(slug),
+            error.unwrap_or_default()
+        ),
+    };
+    let page = small_page(ring, "recheck", &summary);
+    (status, RecheckResponse::Html(page))
+}
+
+/// A page with a heading and a few paragraphs about one thing, linking back to the ring.
+fn small_page(ring: &Ring, title: &str, body: &str) -> RawHtml<String> {
+    html(format!(
+        "
+            <!DOCTYPE html>
+            <html lang='en'>
+                <head>
+                    <meta charset='utf-8'>
+                    <meta name='viewport' content='width=device-width, initial-scale=1.0'>
+                    <title>{ring_name} {title}</title>
+                    <style>
+                        body {{
+                            background: #0b1728;
+                            color: #bdd2ff;
+                            font-family: ui-monospace, Menlo, Consolas, Monaco, Liberation Mono, Lucida Console, monospace;
+                            margin: 0;
+                            padding: 20px;
+                            line-height: 1.4;
+                            box-sizing: border-box;
+                            font-size: 0.8125rem;
+                        }}
+                        h1 {{ margin: 0; }}
+                        p {{ margin: 10px 0; }}
+                        a {{ color: #ff6b60; }}
+                        .dim {{ color: #4a6294; }}
+                        ::selection {{ background: #9d1f15; color: #ffffff; }}
+                    </style>
+                </head>
+                <body>
+                    {body}
+                    <p><a href='{home_url}'>back to {ring_name}</a></p>
+                </body>
+            </html>
+        ",
+        title = title,
+        body = body,
+        ring_name = ring.config().name,
+        home_url = ring.config().path("/"),
+    ))
+}
+
+/// Everything the ring knows about how a member's healthchecks are going, for members looking
+/// into why they dropped out or what they should fix.
+#[get("/health/<slug>")]
+async fn member_health(ring: &Ring, slug: &str) -> Result<RawHtml<String>, Status> {
+    let (member, health) = ring
+        .members
+        .members()
+        .await
+        .into_iter()
+        .find(|(member, _)| member.answers_to(slug))
+        .ok_or(Status::NotFound)?;
+    let uptime = ring.members.uptime().await;
+    let details = ring.members.details().await.remove(&member.slug);
+
+    let body = format!(
+        "
+            <h1>{name}</h1>
+            <p>{verdict}</p>
+            <p>{health}</p>
+            <p class='dim'>checked: {embed_kind} on {url}</p>
+            <p class='dim'>uptime 7d / 30d / 90d: {uptime}</p>
+            <p class='dim'>tls certificate: {certificate}</p>
+            {warnings}
+            <form method='post' action='{recheck_url}'><button>recheck</button></form>
+        ",
+        name = escape_html(&member.name),
+        verdict = if MemberManager::is_in_ring(&member, health.as_ref()) {
+            "in the ring"
+        } else {
+            "not in the ring"
+        },
+        health = match &health {
+            Some(health) => escape_html(&health.to_string()),
+            None => "healthcheck pending...".to_string(),
+        },
+        embed_kind = member.embed_kind,
+        url = escape_html(member.healthcheck_url()),
+        uptime = uptime_fmt(uptime.get(&member.slug)),
+        certificate = match details
+            .as_ref()
+            .and_then(|details| details.certificate_expires_at)
+        {
+            Some(expires_at) => format!(
+                "{} {}",
+                if expires_at <= Utc::now() {
+                    "expired"
+                } else {
+                    "expires"
+                },
+                expires_at.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+            None => "none seen".to_string(),
+        },
+        warnings = warnings_fmt(details.as_ref()),
+        recheck_url = ring
+            .config()
+            .path(&format!("/health/{}/recheck", member.slug)),
+    );
+    Ok(small_page(ring, "health", &body))
+}
+
+#[get("/status.json")]
+async fn status(ring: &Ring) -> RawJson<String> {
+    RawJson(serde_json::to_string(&ring.members.status().await).unwrap())
+}
+
+async fn validate(dir: &Path) -> ExitCode {
+    let (count, diagnostics) = match validate_members(dir).await {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("couldn't read members from {:?}: {}", dir, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if diagnostics.is_empty() {
+        println!("{} member files ok", count);
+        ExitCode::SUCCESS
+    } else {
+        eprintln!(
+            "{} problems found in {} member files",
+            diagnostics.len(),
+            count
+        );
+        ExitCode::FAILURE
+    }
+}
+
+async fn rocket(configs: Vec<RingConfig>) -> anyhow::Result<Rocket<Build>> {
+    let rings = Rings::new(configs)?;
+    for ring in rings.iter() {
+        ring.members.reload().await;
+    }
+
+    // `kill -HUP` forces a reload when the members directory can't be watched.
+    #[cfg(unix)]
+    rocket::tokio::spawn({
+        let rings = rings.clone();
+        async move {
+            use rocket::tokio::signal::unix::{signal, SignalKind};
+
+            let mut hangup = signal(SignalKind::hangup()).expect("couldn't listen for SIGHUP");
+            while hangup.recv().await.is_some() {
+                for ring in rings.iter() {
+                    ring.members.request_reload();
+                }
+            }
+        }
+    });
 
     let cors = CorsOptions {
         allowed_origins: AllowedOrigins::All,
@@ -529,8 +1033,112 @@ async fn rocket() -> _ {
     .to_cors()
     .expect("couldn't create cors options");
 
-    rocket::build()
-        .attach(Shield::new())
-        .mount("/", routes![index, random, embed, members, stats])
-        .attach(cors)
+    // Every ring answers at the root for its own hosts, and rings with a path prefix are also
+    // mounted under it.
+    let prefixes: Vec<String> = rings
+        .iter()
+        .filter_map(|ring| ring.config().path_prefix.clone())
+        .collect();
+    let mut rocket = rocket::build().attach(Shield::new()).manage(rings);
+    for base in std::iter::once("/").chain(prefixes.iter().map(String::as_str)) {
+        rocket = rocket.mount(
+            base,
+            routes![
+                index,
+                random,
+                embed,
+                widget,
+                members,
+                order,
+                status,
+                stats,
+                recheck,
+                member_health
+            ],
+        );
+    }
+    Ok(rocket.attach(cors))
+}
+
+fn load_configs() -> Option<Vec<RingConfig>> {
+    match RingConfig::load_all() {
+        Ok(configs) => Some(configs),
+        Err(err) => {
+            eprintln!("invalid ring config: {:#}", err);
+            None
+        }
+    }
+}
+
+#[rocket::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("validate") {
+        // A directory given explicitly is validated without the ring config, which might be the
+        // thing that's broken.
+        let dirs = match args.get(2) {
+            Some(dir) => vec![PathBuf::from(dir)],
+            None => match load_configs() {
+                Some(configs) => configs
+                    .into_iter()
+                    .map(|config| config.members_dir)
+                    .collect(),
+                None => return ExitCode::FAILURE,
+            },
+        };
+        let mut exit_code = ExitCode::SUCCESS;
+        for dir in dirs {
+            if validate(&dir).await != ExitCode::SUCCESS {
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+        return exit_code;
+    }
+
+    if args.get(1).map(String::as_str) == Some("webhook-key") {
+        let Some(slug) = args.get(2) else {
+            eprintln!("usage: webhook-key <slug>");
+            return ExitCode::FAILURE;
+        };
+        let configs = match load_configs() {
+            Some(configs) => configs,
+            None => return ExitCode::FAILURE,
+        };
+        let mut exit_code = ExitCode::SUCCESS;
+        for config in configs {
+            match config
+                .webhooks
+                .secret
+                .as_deref()
+                .filter(|secret| !secret.is_empty())
+            {
+                Some(secret) => println!("{}: {}", config.name, member_key(secret, slug)),
+                None => {
+                    eprintln!(
+                        "{}: no webhook secret set, so payloads aren't signed",
+                        config.name
+                    );
+                    exit_code = ExitCode::FAILURE;
+                }
+            }
+        }
+        return exit_code;
+    }
+
+    let configs = match load_configs() {
+        Some(configs) => configs,
+        None => return ExitCode::FAILURE,
+    };
+    let rocket = match rocket(configs).await {
+        Ok(rocket) => rocket,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = rocket.launch().await {
+        eprintln!("{}", err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
@@ -1,9 +1,27 @@
-use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, Utc};
 use diesel::{
     sql_query,
-    sql_types::{Integer, Text},
-    Insertable, Queryable, QueryableByName, RunQueryDsl, Selectable, SqliteConnection,
+    sql_types::{Double, Integer, Nullable, Text, TimestamptzSqlite},
+    Connection, ExpressionMethods, Insertable, QueryDsl, Queryable, QueryableByName, RunQueryDsl,
+    Selectable, SqliteConnection,
 };
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::Serialize;
+
+use crate::schema::{health_checks, webhook_deliveries};
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+/// Opens the database at `path`, creating it or bringing its tables up to date first.
+pub fn open_database(path: &str) -> anyhow::Result<SqliteConnection> {
+    let mut db = SqliteConnection::establish(path)
+        .with_context(|| format!("couldn't open database {:?}", path))?;
+    db.run_pending_migrations(MIGRATIONS)
+        .map_err(|err| anyhow!(err))
+        .with_context(|| format!("couldn't migrate database {:?}", path))?;
+    Ok(db)
+}
 
 #[derive(Queryable, Selectable, Clone, Debug)]
 #[diesel(table_name = crate::schema::hits)]
@@ -49,15 +67,32 @@ impl SiteStats {
         }
     }
 
-    pub fn fetch(conn: &mut SqliteConnection) -> Result<Vec<SiteStats>, diesel::result::Error> {
-        sql_query("
-            WITH user_activity AS (
+    /// Computes stats for every slug with hits, counting hits recorded under an alias as hits
+    /// for the slug it points to. `aliases` pairs each alias with its slug.
+    pub fn fetch(
+        conn: &mut SqliteConnection,
+        aliases: &[(&str, &str)],
+    ) -> Result<Vec<SiteStats>, diesel::result::Error> {
+        let mut query = sql_query(format!("
+            WITH aliases(alias, slug) AS (
+                SELECT NULL, NULL WHERE 0
+                {alias_rows}
+            ),
+            folded_hits AS (
+                SELECT
+                    hits.id,
+                    hits.ip_hash,
+                    COALESCE(aliases.slug, hits.slug) AS slug
+                FROM hits
+                LEFT JOIN aliases ON aliases.alias = hits.slug
+            ),
+            user_activity AS (
                 SELECT 
                     ip_hash,
                     slug,
                     -- Slug of the very first visit (lowest ID) for this user
                     FIRST_VALUE(slug) OVER (PARTITION BY ip_hash ORDER BY id ASC) as entry_slug
-                FROM hits
+                FROM folded_hits
             ),
             user_profiles AS (
                 SELECT 
@@ -71,7 +106,7 @@ impl SiteStats {
                 SELECT DISTINCT 
                     slug, 
                     ip_hash 
-                FROM hits
+                FROM folded_hits
             )
             SELECT 
                 uh.slug,
@@ -94,6 +129,122 @@ impl SiteStats {
             FROM unique_hits uh
             JOIN user_profiles up ON uh.ip_hash = up.ip_hash
             GROUP BY uh.slug;
-        ").load(conn)
+        ",
+            alias_rows = " UNION ALL SELECT ?, ?".repeat(aliases.len()),
+        ))
+        .into_boxed();
+        for (alias, slug) in aliases {
+            query = query.bind::<Text, _>(*alias).bind::<Text, _>(*slug);
+        }
+        query.load(conn)
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::health_checks)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewHealthCheck<'a> {
+    pub slug: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub ok: bool,
+    /// The kind of failure, like `site_unreachable`, or `None` if the check passed.
+    pub failure: Option<&'a str>,
+    pub latency_ms: i32,
+}
+
+/// One webhook sent, or given up on, after however many attempts it took.
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::webhook_deliveries)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewWebhookDelivery<'a> {
+    pub url: &'a str,
+    pub event: &'a str,
+    pub slug: &'a str,
+    pub old_state: &'a str,
+    pub new_state: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub attempts: i32,
+    pub ok: bool,
+    /// The receiver's last response status, if it answered at all.
+    pub status: Option<i32>,
+    pub error: Option<&'a str>,
+}
+
+impl NewWebhookDelivery<'_> {
+    /// Deletes deliveries older than healthcheck history is kept for.
+    pub fn prune(conn: &mut SqliteConnection) -> Result<usize, diesel::result::Error> {
+        let cutoff = Utc::now() - Duration::days(HEALTH_HISTORY_DAYS);
+        diesel::delete(webhook_deliveries::table.filter(webhook_deliveries::timestamp.lt(cutoff)))
+            .execute(conn)
+    }
+}
+
+/// How much of the time a member's healthchecks passed, as percentages. Windows without any
+/// checks are `None`.
+#[derive(QueryableByName, Serialize, Debug, Clone)]
+pub struct Uptime {
+    #[diesel(sql_type = Text)]
+    #[serde(skip)]
+    pub slug: String,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub days_7: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub days_30: Option<f64>,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub days_90: Option<f64>,
+}
+
+/// How long healthcheck history is kept, which is also the longest uptime window.
+pub const HEALTH_HISTORY_DAYS: i64 = 90;
+
+impl Uptime {
+    /// Uptime percentages, weighted by time: each check's result holds until the next one, or
+    /// until now for the latest. Failing members are checked less often with backoff, so
+    /// averaging the checks themselves would make them look far healthier than they were.
+    pub fn fetch(conn: &mut SqliteConnection) -> Result<Vec<Uptime>, diesel::result::Error> {
+        let now = Utc::now();
+        sql_query(
+            "
+            WITH spans AS (
+                SELECT
+                    slug,
+                    ok,
+                    julianday(timestamp) AS start,
+                    COALESCE(
+                        LEAD(julianday(timestamp)) OVER (PARTITION BY slug ORDER BY timestamp),
+                        julianday(?1)
+                    ) AS end
+                FROM health_checks
+            ),
+            windows AS (
+                SELECT
+                    slug,
+                    ok,
+                    MAX(0, end - MAX(start, julianday(?2))) AS in_7,
+                    MAX(0, end - MAX(start, julianday(?3))) AS in_30,
+                    MAX(0, end - MAX(start, julianday(?4))) AS in_90
+                FROM spans
+            )
+            SELECT
+                slug,
+                100.0 * SUM(ok * in_7) / NULLIF(SUM(in_7), 0) AS days_7,
+                100.0 * SUM(ok * in_30) / NULLIF(SUM(in_30), 0) AS days_30,
+                100.0 * SUM(ok * in_90) / NULLIF(SUM(in_90), 0) AS days_90
+            FROM windows
+            GROUP BY slug;
+        ",
+        )
+        .bind::<TimestamptzSqlite, _>(now)
+        .bind::<TimestamptzSqlite, _>(now - Duration::days(7))
+        .bind::<TimestamptzSqlite, _>(now - Duration::days(30))
+        .bind::<TimestamptzSqlite, _>(now - Duration::days(HEALTH_HISTORY_DAYS))
+        .load(conn)
+    }
+
+    /// Deletes history older than the longest uptime window.
+    pub fn prune(conn: &mut SqliteConnection) -> Result<usize, diesel::result::Error> {
+        let cutoff = Utc::now() - Duration::days(HEALTH_HISTORY_DAYS);
+        diesel::delete(health_checks::table.filter(health_checks::timestamp.lt(cutoff)))
+            .execute(conn)
     }
 }
@@ -0,0 +1,229 @@
+use std::{convert::TryInto, str::FromStr};
+
+use anyhow::anyhow;
+use chrono::{Datelike, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Member;
+
+/// How often the ring gets reshuffled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rotation {
+    Daily,
+    /// Reshuffled on Mondays, following ISO weeks.
+    Weekly,
+    /// Never reshuffled: members are ordered by when they joined.
+    Fixed,
+}
+
+impl FromStr for Rotation {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "daily" => Ok(Rotation::Daily),
+            "weekly" => Ok(Rotation::Weekly),
+            "fixed" => Ok(Rotation::Fixed),
+            _ => Err(anyhow!(
+                "unknown rotation {:?}, expected daily, weekly or fixed",
+                value
+            )),
+        }
+    }
+}
+
+/// Decides the order members appear in around the ring.
+///
+/// Each member's position comes from the first 8 bytes (big-endian) of
+/// `SHA-256(seed || 0x00 || period || 0x00 || slug)`, where `period` is the date (`2026-02-14`)
+/// for daily rotation or the ISO week (`2026-W07`) for weekly rotation, both in the ring's
+/// timezone. Ties, which shouldn't happen, fall back to the slug. Anyone can recompute the
+/// order from the seed, and it doesn't change between builds or instances.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RingOrder {
+    pub seed: String,
+    pub rotation: Rotation,
+    pub timezone: Tz,
+}
+
+impl Default for RingOrder {
+    fn default() -> Self {
+        Self {
+            seed: String::new(),
+            rotation: Rotation::Daily,
+            timezone: Tz::UTC,
+        }
+    }
+}
+
+impl RingOrder {
+    /// The current date in the ring's timezone, which is when rotations happen.
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.timezone).date_naive()
+    }
+
+    /// The rotation period that `date` falls in.
+    pub fn period(&self, date: NaiveDate) -> String {
+        match self.rotation {
+            Rotation::Daily => date.format("%Y-%m-%d").to_string(),
+            Rotation::Weekly => {
+                let week = date.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Rotation::Fixed => String::new(),
+        }
+    }
+
+    fn key(&self, period: &str, slug: &str) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed.as_bytes());
+        hasher.update([0]);
+        hasher.update(period.as_bytes());
+        hasher.update([0]);
+        hasher.update(slug.as_bytes());
+        let hash = hasher.finalize();
+        u64::from_be_bytes(hash[..8].try_into().unwrap())
+    }
+
+    /// Sorts members into their ring order for `date`.
+    pub fn sort(&self, members: &mut [Member], date: NaiveDate) {
+        match self.rotation {
+            Rotation::Fixed => members.sort_by(|a, b| {
+                // Members without a join date go after everyone who has one.
+                (a.joined.is_none(), a.joined, &a.slug).cmp(&(
+                    b.joined.is_none(),
+                    b.joined,
+                    &b.slug,
+                ))
+            }),
+            _ => {
+                let period = self.period(date);
+                members.sort_by_cached_key(|m| (self.key(&period, &m.slug), m.slug.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{MemberColors, MemberStatus},
+        embed::EmbedKind,
+    };
+
+    const SLUGS: &[&str] = &["alice", "bob", "carol", "dave", "erin"];
+
+    fn order(rotation: Rotation) -> RingOrder {
+        RingOrder {
+            seed: "overengineering".to_string(),
+            rotation,
+            timezone: Tz::UTC,
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn member(slug: &str, joined: Option<NaiveDate>) -> Member {
+        Member {
+            status: MemberStatus::Active,
+            joined,
+            slug: slug.to_string(),
+            aliases: vec![],
+            name: slug.to_string(),
+            url: format!("https://{}.example/", slug),
+            description: None,
+            pronouns: None,
+            tags: vec![],
+            avatar: None,
+            feed: None,
+            healthcheck_url: None,
+            embed_kind: EmbedKind::default(),
+            webhook: None,
+            colors: MemberColors::default(),
+            colors_dark: None,
+            font_stack: None,
+            font_size: None,
+            stylesheets: vec![],
+        }
+    }
+
+    fn sorted(order: &RingOrder, members: &[Member], date: NaiveDate) -> Vec<String> {
+        let mut members = members.to_vec();
+        order.sort(&mut members, date);
+        members.into_iter().map(|member| member.slug).collect()
+    }
+
+    fn shuffled(order: &RingOrder, date: NaiveDate) -> Vec<String> {
+        let members: Vec<Member> = SLUGS.iter().map(|slug| member(slug, None)).collect();
+        sorted(order, &members, date)
+    }
+
+    #[test]
+    fn names_periods() {
+        assert_eq!(
+            order(Rotation::Daily).period(date(2026, 2, 14)),
+            "2026-02-14"
+        );
+        assert_eq!(
+            order(Rotation::Weekly).period(date(2026, 2, 14)),
+            "2026-W07"
+        );
+        // ISO weeks can belong to the year before.
+        assert_eq!(order(Rotation::Weekly).period(date(2027, 1, 1)), "2026-W53");
+        assert_eq!(order(Rotation::Fixed).period(date(2026, 2, 14)), "");
+    }
+
+    #[test]
+    fn keys_match_the_documented_hash() {
+        // First 8 bytes of SHA-256("overengineering\02026-02-14\0alice").
+        assert_eq!(
+            order(Rotation::Daily).key("2026-02-14", "alice"),
+            0x0cb0_6c86_6887_6a63
+        );
+    }
+
+    #[test]
+    fn shuffles_daily() {
+        let order = order(Rotation::Daily);
+        assert_eq!(
+            shuffled(&order, date(2026, 2, 14)),
+            ["alice", "dave", "carol", "bob", "erin"]
+        );
+        assert_eq!(
+            shuffled(&order, date(2026, 2, 15)),
+            ["bob", "erin", "carol", "dave", "alice"]
+        );
+    }
+
+    #[test]
+    fn shuffles_weekly() {
+        let order = order(Rotation::Weekly);
+        let expected = ["bob", "carol", "erin", "dave", "alice"];
+        // Monday to Sunday of the same ISO week.
+        assert_eq!(shuffled(&order, date(2026, 2, 9)), expected);
+        assert_eq!(shuffled(&order, date(2026, 2, 15)), expected);
+        assert_ne!(shuffled(&order, date(2026, 2, 16)), expected);
+    }
+
+    #[test]
+    fn orders_fixed_rings_by_join_date() {
+        let members = [
+            member("alice", None),
+            member("bob", Some(date(2025, 6, 1))),
+            member("carol", Some(date(2024, 1, 1))),
+            member("dave", Some(date(2025, 6, 1))),
+            member("aaron", None),
+        ];
+        assert_eq!(
+            sorted(&order(Rotation::Fixed), &members, date(2026, 2, 14)),
+            ["carol", "bob", "dave", "aaron", "alice"]
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use scraper::{Html, Node};
+use url::Url;
+
+/// Hosts that only serve parking pages and domain sales listings, including their subdomains.
+const PARKING_HOSTS: &[&str] = &[
+    "above.com",
+    "afternic.com",
+    "bodis.com",
+    "dan.com",
+    "domainmarket.com",
+    "hugedomains.com",
+    "parkingcrew.net",
+    "parklogic.com",
+    "sedo.com",
+    "sedoparking.com",
+    "undeveloped.com",
+];
+
+/// Paths of parking page assets on hosts that also serve other things.
+const PARKING_PATHS: &[&str] = &["/parking-lander/", "/parkingpage/"];
+
+/// Phrases from registrars' parking and expiry pages, lowercased.
+const PARKING_PHRASES: &[&str] = &[
+    "this domain is for sale",
+    "this domain may be for sale",
+    "this domain name is for sale",
+    "buy this domain",
+    "this domain has expired",
+    "this domain is parked",
+    "is parked free, courtesy of",
+    "domain parking page",
+];
+
+/// Elements whose text never shows up on the page, like inline scripts that might mention the
+/// phrases for reasons of their own.
+const INVISIBLE_ELEMENTS: &[&str] = &["script", "style", "template", "noscript"];
+
+/// `<meta name>`s whose content says what a page is about.
+const DESCRIPTIVE_META: &[&str] = &["description", "og:title", "og:description"];
+
+fn is_parking_host(host: &str) -> bool {
+    PARKING_HOSTS
+        .iter()
+        .any(|parking| is_same_or_subdomain(host, parking))
+}
+
+fn is_same_or_subdomain(host: &str, parent: &str) -> bool {
+    host == parent
+        || host
+            .strip_suffix(parent)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// Whether `document`, served from `page`, looks like a parking page or a domain for sale rather
+/// than a member's site: it's on a parking service, loads one's assets, or says so in its title,
+/// description or visible text.
+pub fn is_parked(page: &Url, document: &Html) -> bool {
+    if is_parking_host(page.host_str().unwrap_or_default()) {
+        return true;
+    }
+
+    let mut text = String::new();
+    for node in document.tree.nodes() {
+        match node.value() {
+            Node::Element(element) => {
+                let loads_parking_asset = ["src", "href"]
+                    .iter()
+                    .filter_map(|attr| element.attr(attr))
+                    .filter_map(|src| page.join(src.trim()).ok())
+                    .any(|url| {
+                        is_parking_host(url.host_str().unwrap_or_default())
+                            || PARKING_PATHS.iter().any(|path| url.path().contains(path))
+                    });
+                // Linking to a domain marketplace is fine, embedding from one isn't.
+                if loads_parking_asset && element.name() != "a" {
+                    return true;
+                }
+
+                let describes = element.name() == "meta"
+                    && ["name", "property"]
+                        .iter()
+                        .filter_map(|attr| element.attr(attr))
+                        .any(|name| DESCRIPTIVE_META.contains(&name.trim()));
+                if let Some(content) = element.attr("content").filter(|_| describes) {
+                    text.push_str(content);
+                    text.push(' ');
+                }
+            }
+            Node::Text(fragment) => {
+                let invisible = node
+                    .ancestors()
+                    .filter_map(|ancestor| ancestor.value().as_element())
+                    .any(|ancestor| INVISIBLE_ELEMENTS.contains(&ancestor.name()));
+                if invisible {
+                    continue;
+                }
+                text.push_str(fragment);
+                text.push(' ');
+            }
+            _ => {}
+        }
+    }
+
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+    PARKING_PHRASES.iter().any(|phrase| text.contains(phrase))
+}
+
+/// Whether two hosts belong to the same site, ignoring `www.` and counting subdomains of each
+/// other, so `example.com` redirecting to `blog.example.com` isn't treated as a move.
+pub fn is_same_site(a: &str, b: &str) -> bool {
+    let (a, b) = (a.to_ascii_lowercase(), b.to_ascii_lowercase());
+    let (a, b) = (a.trim_start_matches("www."), b.trim_start_matches("www."));
+    is_same_or_subdomain(a, b) || is_same_or_subdomain(b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parked_at(page: &str, html: &str) -> bool {
+        is_parked(&Url::parse(page).unwrap(), &Html::parse_document(html))
+    }
+
+    fn parked(html: &str) -> bool {
+        parked_at("https://member.example/", html)
+    }
+
+    #[test]
+    fn spots_parking_hosts() {
+        assert!(parked_at(
+            "https://sedo.com/search?domain=member.example",
+            ""
+        ));
+        assert!(parked_at(
+            "https://www.HugeDomains.com/domain_profile.cfm",
+            ""
+        ));
+        assert!(!parked_at("https://notsedo.com/", ""));
+        assert!(!parked_at("https://sedo.com.member.example/", ""));
+    }
+
+    #[test]
+    fn spots_parking_phrases() {
+        assert!(parked(
+            "<title>member.example</title><h1>This   domain\nis <b>for sale</b>!</h1>"
+        ));
+        assert!(parked(
+            r#"<head><meta name="description" content="Buy this domain today"></head>"#
+        ));
+        assert!(parked(
+            r#"<head><meta property="og:title" content="This domain has expired"></head>"#
+        ));
+        assert!(!parked(
+            r#"<head><meta name="keywords" content="buy this domain"></head><p>My blog</p>"#
+        ));
+        assert!(!parked("<p>I bought this domain years ago.</p>"));
+    }
+
+    #[test]
+    fn ignores_text_nobody_sees() {
+        assert!(!parked(
+            r#"<script>if (sold) alert("This domain is for sale")</script><p>My blog</p>"#
+        ));
+        assert!(!parked(
+            r#"<style>/* this domain is parked */</style><noscript>this domain is parked</noscript>"#
+        ));
+        assert!(!parked(
+            "<template><p>Buy this domain</p></template><p>My blog</p>"
+        ));
+    }
+
+    #[test]
+    fn spots_parking_assets_but_not_links() {
+        assert!(parked(
+            r#"<script src="https://img.sedoparking.com/js/lander.js"></script>"#
+        ));
+        assert!(parked(
+            r#"<link rel="stylesheet" href="//www.parkingcrew.net/assets/style.css">"#
+        ));
+        assert!(parked(r#"<img src="/parking-lander/logo.png">"#));
+        assert!(!parked(
+            r#"<p>I bought this from <a href="https://dan.com/">dan.com</a>.</p>"#
+        ));
+        assert!(!parked(
+            r#"<a href="https://sedo.com/parkingpage/">how parking works</a>"#
+        ));
+    }
+
+    #[test]
+    fn compares_sites() {
+        assert!(is_same_site("member.example", "member.example"));
+        assert!(is_same_site("www.member.example", "member.example"));
+        assert!(is_same_site("WWW.Member.Example", "member.example"));
+        assert!(is_same_site("member.example", "blog.member.example"));
+        assert!(is_same_site("blog.member.example", "www.member.example"));
+        assert!(!is_same_site("member.example", "othermember.example"));
+        assert!(!is_same_site("member.example", "sedo.com"));
+        assert!(!is_same_site("member.example", "member.example.sedo.com"));
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::HashSet,
+    env,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use diesel::SqliteConnection;
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    tokio::sync::Mutex,
+};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{config::check_url, health::MemberManager, models::open_database, order::RingOrder};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HealthcheckConfig {
+    /// Seconds between healthcheck runs.
+    pub interval: u64,
+    /// How many member sites are checked at once.
+    pub concurrency: usize,
+    /// Seconds to wait for a member site before giving up.
+    pub timeout: u64,
+    /// Extra attempts when a member site can't be reached, before the check counts as failed.
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry, doubling for each retry after that.
+    pub retry_backoff: u64,
+    /// Failed checks in a row before a member drops out of the ring.
+    pub failures_to_drop: u32,
+    /// Passed checks in a row before a member who dropped out comes back.
+    pub successes_to_return: u32,
+    /// Bytes of a member page to read before giving up on finding the embed.
+    pub max_body_size: usize,
+    /// Redirects to follow before calling the page unreachable.
+    pub max_redirects: usize,
+    /// Longest wait between checks of a member who's been out of the ring for a while, in seconds.
+    pub max_backoff: u64,
+    /// How long a member has to wait between rechecks they ask for, in seconds.
+    pub recheck_cooldown: u64,
+    /// Seconds before a member stylesheet is checked again.
+    pub stylesheet_interval: u64,
+    /// Bytes a member stylesheet can be before it's reported as too big.
+    pub max_stylesheet_size: usize,
+    /// Days before a member's TLS certificate runs out to start warning about it.
+    pub certificate_warning_days: i64,
+}
+
+impl Default for HealthcheckConfig {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            concurrency: 8,
+            timeout: 5,
+            retries: 2,
+            retry_backoff: 500,
+            failures_to_drop: 3,
+            successes_to_return: 2,
+            max_body_size: 1024 * 1024,
+            max_redirects: 5,
+            max_backoff: 6 * 60 * 60,
+            recheck_cooldown: 60,
+            stylesheet_interval: 60 * 60,
+            max_stylesheet_size: 256 * 1024,
+            certificate_warning_days: 14,
+        }
+    }
+}
+
+impl HealthcheckConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_secs(self.max_backoff)
+    }
+
+    pub fn recheck_cooldown(&self) -> Duration {
+        Duration::from_secs(self.recheck_cooldown)
+    }
+
+    pub fn stylesheet_interval(&self) -> Duration {
+        Duration::from_secs(self.stylesheet_interval)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+
+    pub fn retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_backoff)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Where every member's healthcheck transitions are sent, on top of members' own webhooks.
+    pub urls: Vec<String>,
+    /// Key for signing payloads, so receivers can tell they came from the ring.
+    pub secret: Option<String>,
+    /// Extra delivery attempts when a receiver fails or can't be reached.
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubling after each one.
+    pub retry_backoff: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            urls: vec![],
+            secret: None,
+            retries: 3,
+            retry_backoff: 1000,
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_backoff)
+    }
+}
+
+/// Everything that makes one webring different from another. The defaults are overengineeRING's
+/// own settings, so an empty config file runs the original ring.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RingConfig {
+    pub name: String,
+    /// Public URL the ring is served from, without a trailing slash. Member sites are expected
+    /// to embed `{base_url}/embed/<slug>`.
+    pub base_url: String,
+    pub banner: String,
+    /// Shown under the banner. Can contain HTML.
+    pub tagline: String,
+    /// Paragraphs introducing the ring at the top of the member list. Can contain HTML.
+    pub description: Vec<String>,
+    /// Shown under the healthcheck failures table, if set. Can contain HTML.
+    pub failures_note: Option<String>,
+    pub contact_email: String,
+    /// GitHub repository that member config files are submitted to.
+    pub repository: String,
+    /// Rules for joining, one list item each. Can contain HTML.
+    pub criteria: Vec<String>,
+    pub database: PathBuf,
+    pub members_dir: PathBuf,
+    pub healthcheck: HealthcheckConfig,
+    pub webhooks: WebhookConfig,
+    pub order: RingOrder,
+    /// Host names this ring answers on when one server hosts several rings, without ports.
+    pub hosts: Vec<String>,
+    /// Path this ring is served under when one server hosts several rings, like `/staging`.
+    pub path_prefix: Option<String>,
+}
+
+impl Default for RingConfig {
+    fn default() -> Self {
+        Self {
+            name: "overengineeRING".to_string(),
+            base_url: "https://overengineering.kognise.dev".to_string(),
+            banner: "https://raw.githubusercontent.com/kognise/overengineering/main/banner.png"
+                .to_string(),
+            tagline: "overengineeRING 2: now with healthchecks, cooler people, and seasonal themes!"
+                .to_string(),
+            description: vec![
+                "a <a href='https://en.wikipedia.org/wiki/Webring' target='_blank' rel='noopener noreferrer'>webring</a> of interesting people; makers of technology, music, art, or writing. (<a href='https://github.com/kognise/overengineering/' target='_blank' rel='noopener noreferrer'>github</a>)".to_string(),
+                "everyone on this list has different skill levels and different personalities, but i guarantee you'll get something out of talking to them or looking at their sites.".to_string(),
+            ],
+            failures_note: Some("(the plurality of dead members is partially caused by some serious downtime over the past couple of years. v2 fixes this!)".to_string()),
+            contact_email: "hi@kognise.dev".to_string(),
+            repository: "https://github.com/kognise/overengineering".to_string(),
+            criteria: [
+                "this is a webring containing personal sites only.",
+                "you should be an interesting person! a great gauge is whether you think people will get something out of visiting your website, whether inspiration or curiosity.",
+                "no illegal, nsfw, or gory content is allowed. duh.",
+                "members must embed the webring widget on the homepage of their site.",
+                "don't be evil, unless you really have to.",
+                "if you're part of multiple webrings, this should be the most prominent. (i know some other webrings don't do this but people find it really confusing otherwise, sorry :<)",
+            ]
+            .iter()
+            .map(|criterion| criterion.to_string())
+            .collect(),
+            database: PathBuf::from("./stats.db"),
+            members_dir: PathBuf::from("members"),
+            healthcheck: HealthcheckConfig::default(),
+            webhooks: WebhookConfig::default(),
+            order: RingOrder::default(),
+            hosts: vec![],
+            path_prefix: None,
+        }
+    }
+}
+
+fn env_override<T: FromStr>(name: &str, target: &mut T) -> anyhow::Result<()>
+where
+    T::Err: Display,
+{
+    if let Ok(value) = env::var(name) {
+        *target = value
+            .parse()
+            .map_err(|err| anyhow!("invalid {}: {}", name, err))?;
+    }
+    Ok(())
+}
+
+impl RingConfig {
+    /// Loads the rings listed in `RING_CONFIG`, separated by commas (`ring.yaml` by default,
+    /// which doesn't have to exist). With a single ring, any `RING_*` environment variable
+    /// overrides are applied on top; with several they'd be ambiguous, so they're ignored.
+    pub fn load_all() -> anyhow::Result<Vec<Self>> {
+        let paths: Vec<PathBuf> = match env::var("RING_CONFIG") {
+            Ok(paths) => paths
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            Err(_) => vec![],
+        };
+
+        let mut configs = if paths.is_empty() {
+            vec![Self::load_file(Path::new("ring.yaml"), true)?]
+        } else {
+            paths
+                .iter()
+                .map(|path| Self::load_file(path, false))
+                .collect::<anyhow::Result<_>>()?
+        };
+        if let [config] = configs.as_mut_slice() {
+            config.apply_env()?;
+        }
+
+        let mut hosts = HashSet::new();
+        let mut prefixes = HashSet::new();
+        for config in &mut configs {
+            config.normalize();
+            Url::parse(&config.embed_prefix())
+                .with_context(|| format!("invalid base_url {:?}", config.base_url))?;
+            for url in &config.webhooks.urls {
+                check_url(url).map_err(|err| anyhow!("invalid webhook: {}", err))?;
+            }
+            for host in &config.hosts {
+                if !hosts.insert(host.clone()) {
+                    bail!("host {:?} is used by more than one ring", host);
+                }
+            }
+            if let Some(prefix) = &config.path_prefix {
+                if !prefixes.insert(prefix.clone()) {
+                    bail!("path prefix {:?} is used by more than one ring", prefix);
+                }
+            }
+        }
+        Ok(configs)
+    }
+
+    fn load_file(path: &Path, optional: bool) -> anyhow::Result<Self> {
+        match std::fs::File::open(path) {
+            Ok(reader) => serde_yaml::from_reader(reader)
+                .with_context(|| format!("couldn't parse ring config {:?}", path)),
+            Err(err) if optional && err.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(err) => Err(err).with_context(|| format!("couldn't open ring config {:?}", path)),
+        }
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        env_override("RING_NAME", &mut self.name)?;
+        env_override("RING_BASE_URL", &mut self.base_url)?;
+        env_override("RING_BANNER", &mut self.banner)?;
+        env_override("RING_CONTACT_EMAIL", &mut self.contact_email)?;
+        env_override("RING_REPOSITORY", &mut self.repository)?;
+        env_override("RING_DATABASE", &mut self.database)?;
+        env_override("RING_MEMBERS_DIR", &mut self.members_dir)?;
+        env_override("RING_HEALTHCHECK_INTERVAL", &mut self.healthcheck.interval)?;
+        env_override(
+            "RING_HEALTHCHECK_CONCURRENCY",
+            &mut self.healthcheck.concurrency,
+        )?;
+        env_override("RING_HEALTHCHECK_TIMEOUT", &mut self.healthcheck.timeout)?;
+        env_override("RING_HEALTHCHECK_RETRIES", &mut self.healthcheck.retries)?;
+        env_override(
+            "RING_HEALTHCHECK_RETRY_BACKOFF",
+            &mut self.healthcheck.retry_backoff,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_FAILURES_TO_DROP",
+            &mut self.healthcheck.failures_to_drop,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_SUCCESSES_TO_RETURN",
+            &mut self.healthcheck.successes_to_return,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_MAX_BODY_SIZE",
+            &mut self.healthcheck.max_body_size,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_MAX_REDIRECTS",
+            &mut self.healthcheck.max_redirects,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_MAX_BACKOFF",
+            &mut self.healthcheck.max_backoff,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_RECHECK_COOLDOWN",
+            &mut self.healthcheck.recheck_cooldown,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_STYLESHEET_INTERVAL",
+            &mut self.healthcheck.stylesheet_interval,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_MAX_STYLESHEET_SIZE",
+            &mut self.healthcheck.max_stylesheet_size,
+        )?;
+        env_override(
+            "RING_HEALTHCHECK_CERTIFICATE_WARNING_DAYS",
+            &mut self.healthcheck.certificate_warning_days,
+        )?;
+        if let Ok(secret) = env::var("RING_WEBHOOK_SECRET") {
+            self.webhooks.secret = Some(secret);
+        }
+        env_override("RING_SEED", &mut self.order.seed)?;
+        env_override("RING_ROTATION", &mut self.order.rotation)?;
+        env_override("RING_TIMEZONE", &mut self.order.timezone)?;
+        Ok(())
+    }
+
+    fn normalize(&mut self) {
+        self.base_url = self.base_url.trim_end_matches('/').to_string();
+        self.repository = self.repository.trim_end_matches('/').to_string();
+        let healthcheck = &mut self.healthcheck;
+        healthcheck.concurrency = healthcheck.concurrency.max(1);
+        healthcheck.failures_to_drop = healthcheck.failures_to_drop.max(1);
+        healthcheck.successes_to_return = healthcheck.successes_to_return.max(1);
+        for host in &mut self.hosts {
+            *host = host.to_ascii_lowercase();
+        }
+        self.path_prefix = self
+            .path_prefix
+            .as_deref()
+            .map(|prefix| prefix.trim_matches('/'))
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| format!("/{}", prefix));
+    }
+
+    /// The embed URL prefix member sites are expected to link to.
+    pub fn embed_prefix(&self) -> String {
+        format!("{}/embed/", self.base_url)
+    }
+
+    /// `embed_prefix` as a URL. `load_all` makes sure it parses.
+    pub fn embed_url(&self) -> Url {
+        Url::parse(&self.embed_prefix()).expect("invalid base_url")
+    }
+
+    /// How the ring introduces itself to the sites it makes requests to.
+    pub fn user_agent(&self) -> String {
+        format!(
+            "overengineering/{} (+{}; {} healthcheck)",
+            env!("CARGO_PKG_VERSION"),
+            self.base_url,
+            self.name
+        )
+    }
+
+    /// An absolute path on this ring's pages, under its path prefix if it has one.
+    pub fn path(&self, path: &str) -> String {
+        format!(
+            "{}{}",
+            self.path_prefix.as_deref().unwrap_or_default(),
+            path
+        )
+    }
+}
+
+/// One running webring: its members, their healthchecks and its stats database.
+pub struct Ring {
+    pub members: MemberManager,
+    pub db: Arc<Mutex<SqliteConnection>>,
+}
+
+impl Ring {
+    pub fn new(config: RingConfig) -> anyhow::Result<Self> {
+        let db = Arc::new(Mutex::new(open_database(
+            &config.database.to_string_lossy(),
+        )?));
+        Ok(Self {
+            members: MemberManager::new(config, db.clone()),
+            db,
+        })
+    }
+
+    pub fn config(&self) -> &RingConfig {
+        self.members.config()
+    }
+}
+
+/// Every ring served by this process. The first one is the default, used for requests that no
+/// other ring claims.
+#[derive(Clone)]
+pub struct Rings(Arc<Vec<Ring>>);
+
+impl Rings {
+    pub fn new(configs: Vec<RingConfig>) -> anyhow::Result<Self> {
+        if configs.is_empty() {
+            bail!("no rings configured");
+        }
+        let rings = configs
+            .into_iter()
+            .map(Ring::new)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(Arc::new(rings)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Ring> {
+        self.0.iter()
+    }
+
+    /// Picks the ring for a request: the one mounted at the route's path prefix, then the one
+    /// claiming the request's host, then the default.
+    pub fn resolve(&self, host: Option<&str>, mount_point: &str) -> &Ring {
+        self.0
+            .iter()
+            .find(|ring| ring.config().path_prefix.as_deref() == Some(mount_point))
+            .or_else(|| {
+                let host = host?;
+                self.0.iter().find(|ring| {
+                    ring.config()
+                        .hosts
+                        .iter()
+                        .any(|ring_host| ring_host.eq_ignore_ascii_case(host))
+                })
+            })
+            .unwrap_or(&self.0[0])
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Ring {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rings = match request.rocket().state::<Rings>() {
+            Some(rings) => rings,
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        let host = request.host().map(|host| host.domain().as_str());
+        let mount_point = request.route().map(|route| route.uri.base()).unwrap_or("/");
+        Outcome::Success(rings.resolve(host, mount_point))
+    }
+}
@@ -1,5 +1,16 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    health_checks (id) {
+        id -> Integer,
+        slug -> Text,
+        timestamp -> TimestamptzSqlite,
+        ok -> Bool,
+        failure -> Nullable<Text>,
+        latency_ms -> Integer,
+    }
+}
+
 diesel::table! {
     hits (id) {
         id -> Integer,
@@ -8,3 +19,21 @@ diesel::table! {
         timestamp -> TimestamptzSqlite,
     }
 }
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Integer,
+        url -> Text,
+        slug -> Text,
+        old_state -> Text,
+        new_state -> Text,
+        timestamp -> TimestamptzSqlite,
+        attempts -> Integer,
+        ok -> Bool,
+        status -> Nullable<Integer>,
+        error -> Nullable<Text>,
+        event -> Text,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(health_checks, hits, webhook_deliveries,);
@@ -0,0 +1,381 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{
+        check_description, check_pronouns, check_slug, check_tag, check_url, member_files,
+        slug_from_path, MemberFormat, ParsedMemberStatus, MAX_TAGS,
+    },
+    css::{Color, FontStack, Length},
+};
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: {}",
+            self.file.display(),
+            self.field,
+            self.message
+        )
+    }
+}
+
+/// Loads a single member file the same way the server does and reports every problem found.
+pub fn validate_member_file(path: &Path) -> Vec<Diagnostic> {
+    check_member_file(path).0
+}
+
+/// Like `validate_member_file`, but also returns the slug and aliases the file claims, slug first.
+fn check_member_file(path: &Path) -> (Vec<Diagnostic>, Vec<String>) {
+    let mut diagnostics = vec![];
+    let mut claims = vec![];
+    let mut report = |field: &str, message: String| {
+        diagnostics.push(Diagnostic {
+            file: path.to_path_buf(),
+            field: field.to_string(),
+            message,
+        })
+    };
+
+    let format = match MemberFormat::from_path(path) {
+        Some(format) => format,
+        None => {
+            report(
+                "file",
+                "member files must end in .yaml, .yml, .toml or .json".to_string(),
+            );
+            return (diagnostics, claims);
+        }
+    };
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            report("file", format!("couldn't open file: {}", err));
+            return (diagnostics, claims);
+        }
+    };
+    let mut unknown = vec![];
+    let member = match format.parse_noting_unknown(&contents, |key| unknown.push(key)) {
+        Ok(member) => member,
+        Err(err) => {
+            report("format", err.to_string());
+            return (diagnostics, claims);
+        }
+    };
+    // The server skips these, so a typo would otherwise quietly leave a setting at its default.
+    for key in unknown {
+        report(&key, "not a member setting, check the spelling".to_string());
+    }
+
+    let slug = member.slug(path);
+    match check_slug(&slug) {
+        Ok(()) => claims.push(slug),
+        Err(message) if member.slug.is_none() => report(
+            "slug",
+            format!(
+                "{} (taken from the file name {:?})",
+                message,
+                slug_from_path(path)
+            ),
+        ),
+        Err(message) => report("slug", message),
+    }
+    for (index, alias) in member.aliases.iter().flatten().enumerate() {
+        match check_slug(alias) {
+            // A file without a valid slug is skipped, so its aliases don't claim anything.
+            Ok(()) if claims.is_empty() || claims.contains(alias) => {}
+            Ok(()) => claims.push(alias.clone()),
+            Err(message) => report(&format!("aliases[{}]", index), message),
+        }
+    }
+
+    if member.is_being_removed.is_some() {
+        report(
+            "is_being_removed",
+            "is_being_removed is deprecated, use `status: removed` instead".to_string(),
+        );
+    }
+    if member.hiatus_until.is_some() && member.status != Some(ParsedMemberStatus::Hiatus) {
+        report(
+            "hiatus_until",
+            "hiatus_until is only used with `status: hiatus`".to_string(),
+        );
+    }
+
+    if member.name.trim().is_empty() {
+        report("name", "name must not be empty".to_string());
+    }
+    if let Err(message) = check_url(&member.url) {
+        report("url", message);
+    }
+    for (index, stylesheet) in member.stylesheets.iter().flatten().enumerate() {
+        if let Err(message) = check_url(stylesheet) {
+            report(&format!("stylesheets[{}]", index), message);
+        }
+    }
+
+    if let Some(Err(message)) = member.description.as_deref().map(check_description) {
+        report("description", message);
+    }
+    if let Some(Err(message)) = member.pronouns.as_deref().map(check_pronouns) {
+        report("pronouns", message);
+    }
+    if let Some(Err(message)) = member.avatar.as_deref().map(check_url) {
+        report("avatar", message);
+    }
+    if let Some(Err(message)) = member.feed.as_deref().map(check_url) {
+        report("feed", message);
+    }
+    if let Some(Err(message)) = member.healthcheck_url.as_deref().map(check_url) {
+        report("healthcheck_url", message);
+    }
+    if let Some(Err(message)) = member.webhook.as_deref().map(check_url) {
+        report("webhook", message);
+    }
+    let tags = member.tags.as_deref().unwrap_or_default();
+    if tags.len() > MAX_TAGS {
+        report(
+            "tags",
+            format!("{} tags given, the limit is {}", tags.len(), MAX_TAGS),
+        );
+    }
+    for (index, tag) in tags.iter().enumerate() {
+        if let Err(message) = check_tag(tag) {
+            report(&format!("tags[{}]", index), message);
+        }
+    }
+
+    for (block, colors) in [
+        ("colors", &member.colors),
+        ("colors_dark", &member.colors_dark),
+    ] {
+        let colors = match colors {
+            Some(colors) => colors,
+            None => continue,
+        };
+        for (name, value) in [
+            ("text", &colors.text),
+            ("border", &colors.border),
+            ("links", &colors.links),
+            ("on_links", &colors.on_links),
+        ] {
+            if let Some(Err(err)) = value.as_ref().map(|value| value.parse::<Color>()) {
+                report(&format!("{}.{}", block, name), err.to_string());
+            }
+        }
+    }
+
+    if let Some(Err(err)) = member.font_size.as_ref().map(|v| v.parse::<Length>()) {
+        report("font_size", err.to_string());
+    }
+    if let Some(Err(err)) = member.font_stack.as_ref().map(|v| v.parse::<FontStack>()) {
+        report("font_stack", err.to_string());
+    }
+
+    (diagnostics, claims)
+}
+
+/// Validates every member file in a members directory, returning the number of files checked.
+pub async fn validate_members(dir: &Path) -> anyhow::Result<(usize, Vec<Diagnostic>)> {
+    let paths = member_files(dir).await?;
+
+    let mut diagnostics = vec![];
+    let mut claimed: HashMap<String, &Path> = HashMap::new();
+    let mut aliases = vec![];
+    // Slugs first, like `read_members`, so only aliases lose out to them.
+    for (path, _) in &paths {
+        let (file_diagnostics, claims) = check_member_file(path);
+        diagnostics.extend(file_diagnostics);
+        let mut claims = claims.into_iter();
+        let Some(slug) = claims.next() else {
+            continue;
+        };
+        match claimed.get(&slug) {
+            Some(other) => diagnostics.push(Diagnostic {
+                file: path.clone(),
+                field: "slug".to_string(),
+                message: format!("slug {:?} is already used by {}", slug, other.display()),
+            }),
+            None => {
+                claimed.insert(slug, path);
+                aliases.push((path, claims));
+            }
+        }
+    }
+    for (path, claims) in aliases {
+        for alias in claims {
+            match claimed.get(&alias) {
+                Some(other) => diagnostics.push(Diagnostic {
+                    file: path.clone(),
+                    field: "aliases".to_string(),
+                    message: format!(
+                        "alias {:?} is already used by {}, and will be ignored",
+                        alias,
+                        other.display()
+                    ),
+                }),
+                None => {
+                    claimed.insert(alias, path);
+                }
+            }
+        }
+    }
+    Ok((paths.len(), diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::read_members;
+
+    type Members = Vec<(String, Vec<String>)>;
+    type Conflicts = Vec<(String, String)>;
+
+    /// Writes `files` into a fresh members directory, then returns what `read_members` loads
+    /// from it and which files and fields `validate_members` complains about.
+    async fn load(name: &str, files: &[(&str, &str)]) -> (Members, Conflicts) {
+        let dir =
+            std::env::temp_dir().join(format!("ring-validate-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, extra) in files {
+            let contents = format!("name: Someone\nurl: https://someone.example/\n{}", extra);
+            std::fs::write(dir.join(file), contents).unwrap();
+        }
+
+        let members = read_members(&dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|member| (member.slug, member.aliases))
+            .collect();
+        let (_, diagnostics) = validate_members(&dir).await.unwrap();
+        let conflicts = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let file = diagnostic.file.file_name().unwrap().to_string_lossy();
+                (file.into_owned(), diagnostic.field)
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (members, conflicts)
+    }
+
+    fn owned(members: &[(&str, &[&str])], conflicts: &[(&str, &str)]) -> (Members, Conflicts) {
+        (
+            members
+                .iter()
+                .map(|(slug, aliases)| {
+                    let aliases = aliases.iter().map(|alias| alias.to_string()).collect();
+                    (slug.to_string(), aliases)
+                })
+                .collect(),
+            conflicts
+                .iter()
+                .map(|(file, field)| (file.to_string(), field.to_string()))
+                .collect(),
+        )
+    }
+
+    #[rocket::async_test]
+    async fn skips_duplicate_slugs() {
+        let loaded = load(
+            "duplicate-slug",
+            &[("a.yaml", "slug: shared\n"), ("b.yaml", "slug: shared\n")],
+        )
+        .await;
+        assert_eq!(loaded, owned(&[("shared", &[])], &[("b.yaml", "slug")]));
+    }
+
+    #[rocket::async_test]
+    async fn drops_aliases_of_other_members_slugs() {
+        // The slug wins even though its file sorts after the alias's.
+        let loaded = load(
+            "alias-is-slug",
+            &[("a.yaml", "aliases: [b]\n"), ("b.yaml", "")],
+        )
+        .await;
+        assert_eq!(
+            loaded,
+            owned(&[("a", &[]), ("b", &[])], &[("a.yaml", "aliases")])
+        );
+    }
+
+    #[rocket::async_test]
+    async fn keeps_the_first_claim_to_an_alias() {
+        let loaded = load(
+            "alias-twice",
+            &[
+                ("a.yaml", "aliases: [old]\n"),
+                ("b.yaml", "aliases: [old]\n"),
+            ],
+        )
+        .await;
+        assert_eq!(
+            loaded,
+            owned(&[("a", &["old"]), ("b", &[])], &[("b.yaml", "aliases")])
+        );
+    }
+
+    #[rocket::async_test]
+    async fn ignores_aliases_of_skipped_files() {
+        let loaded = load(
+            "skipped-aliases",
+            &[
+                ("a.yaml", "slug: not a slug\naliases: [old]\n"),
+                ("b.yaml", "aliases: [old]\n"),
+            ],
+        )
+        .await;
+        assert_eq!(loaded, owned(&[("b", &["old"])], &[("a.yaml", "slug")]));
+    }
+
+    #[rocket::async_test]
+    async fn reports_unknown_settings() {
+        let loaded = load(
+            "unknown-settings",
+            &[(
+                "a.yaml",
+                "colour: red\nfont-size: 2em\ncolors:\n  txt: \"#fff\"\n",
+            )],
+        )
+        .await;
+        // The server still loads the member, it just doesn't use those settings.
+        assert_eq!(
+            loaded,
+            owned(
+                &[("a", &[])],
+                &[
+                    ("a.yaml", "colour"),
+                    ("a.yaml", "font-size"),
+                    ("a.yaml", "colors.txt")
+                ]
+            )
+        );
+
+        let mut unknown = vec![];
+        let toml = "name = \"b\"\nurl = \"https://b.example/\"\nstylesheet = \"x\"\n\n[colors_dark]\nlinkz = \"#000\"\n";
+        MemberFormat::Toml
+            .parse_noting_unknown(toml.as_bytes(), |key| unknown.push(key))
+            .unwrap();
+        assert_eq!(unknown, ["colors_dark.linkz", "stylesheet"]);
+
+        let mut unknown = vec![];
+        let json = r#"{"name": "c", "url": "https://c.example/", "tag": ["x"]}"#;
+        MemberFormat::Json
+            .parse_noting_unknown(json.as_bytes(), |key| unknown.push(key))
+            .unwrap();
+        assert_eq!(unknown, ["tag"]);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{convert::TryInto, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use diesel::{RunQueryDsl, SqliteConnection};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use rocket::tokio::{spawn, sync::Mutex, time::sleep};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{
+    models::NewWebhookDelivery,
+    ring::{RingConfig, WebhookConfig},
+    schema::webhook_deliveries,
+};
+
+/// Something about a member changing state, as sent to webhooks.
+#[derive(Serialize, Debug, Clone)]
+pub struct Transition {
+    /// What changed: `health`, whose states are `ok` or the kind of failure, like `timeout`, or
+    /// `certificate`, whose states are `valid`, `expiring` and `expired`, starting from
+    /// `unknown` when a member's certificate is first seen already expiring or expired.
+    pub event: &'static str,
+    pub ring: String,
+    pub slug: String,
+    pub old_state: &'static str,
+    pub new_state: &'static str,
+    /// Why the member is in their new state, in words.
+    pub reason: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Sends transitions to the ring's webhooks and members' own, logging every delivery
+/// in the ring's database.
+///
+/// When the ring has a secret, payloads are signed with HMAC-SHA256, with the hex digest of the
+/// body in `X-Ring-Signature: sha256=<digest>`. Payloads to the ring's own webhooks are signed
+/// with the secret itself, and those to a member's webhook with their `member_key`, so no member
+/// can sign payloads for the ring or anyone else.
+pub struct Webhooks {
+    client: Client,
+    config: WebhookConfig,
+    db: Arc<Mutex<SqliteConnection>>,
+}
+
+impl Webhooks {
+    pub fn new(config: &RingConfig, db: Arc<Mutex<SqliteConnection>>) -> Self {
+        let client = Client::builder()
+            .user_agent(config.user_agent())
+            .timeout(config.healthcheck.timeout())
+            .build()
+            .unwrap();
+        Self {
+            client,
+            config: config.webhooks.clone(),
+            db,
+        }
+    }
+
+    /// Sends `transition` in the background to every ring-wide webhook, and to `member_webhook`
+    /// if the member has one.
+    pub fn send(self: &Arc<Self>, transition: Transition, member_webhook: Option<&str>) {
+        let body = serde_json::to_string(&transition).unwrap();
+        let transition = Arc::new(transition);
+        let ring_webhooks = self
+            .config
+            .urls
+            .iter()
+            .map(|url| (url.as_str(), Recipient::Ring));
+        let member_webhook = member_webhook.map(|url| (url, Recipient::Member));
+        for (url, recipient) in ring_webhooks.chain(member_webhook) {
+            let webhooks = self.clone();
+            let url = url.to_string();
+            let body = body.clone();
+            let transition = transition.clone();
+            spawn(async move { webhooks.deliver(&url, &transition, body, recipient).await });
+        }
+    }
+
+    /// The key payloads about `slug` are signed with for `recipient`, if the ring has a secret.
+    fn signing_key(&self, recipient: Recipient, slug: &str) -> Option<String> {
+        let secret = self
+            .config
+            .secret
+            .as_deref()
+            .filter(|secret| !secret.is_empty())?;
+        Some(match recipient {
+            Recipient::Ring => secret.to_string(),
+            Recipient::Member => member_key(secret, slug),
+        })
+    }
+
+    /// Posts `body` to `url`, retrying with exponential backoff while the receiver is down or
+    /// erroring, then logs how it went.
+    async fn deliver(
+        &self,
+        url: &str,
+        transition: &Transition,
+        body: String,
+        recipient: Recipient,
+    ) {
+        let signature = self
+            .signing_key(recipient, &transition.slug)
+            .map(|key| format!("sha256={}", hmac_hex(&key, &body)));
+        let mut backoff = self.config.retry_backoff();
+        let mut attempts = 0;
+        let (status, error) = loop {
+            attempts += 1;
+            let mut request = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header("X-Ring-Signature", signature);
+            }
+
+            let (status, error) = match request.send().await {
+                Ok(response) if response.status().is_success() => (Some(response.status()), None),
+                Ok(response) => (
+                    Some(response.status()),
+                    Some(format!("receiver answered {}", response.status())),
+                ),
+                Err(err) => (None, Some(err.to_string())),
+            };
+            // Receivers that reject the payload outright won't change their minds.
+            let retryable = status.is_none_or(|status| {
+                status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+            });
+            if error.is_none() || !retryable || attempts > self.config.retries {
+                break (status, error);
+            }
+            sleep(backoff).await;
+            backoff *= 2;
+        };
+
+        if let Some(error) = &error {
+            eprintln!(
+                "Couldn't deliver {} webhook to {}, giving up after attempt {}: {}",
+                transition.slug, url, attempts, error
+            );
+        }
+        let delivery = NewWebhookDelivery {
+            url,
+            event: transition.event,
+            slug: &transition.slug,
+            old_state: transition.old_state,
+            new_state: transition.new_state,
+            timestamp: Utc::now(),
+            attempts: attempts.try_into().unwrap_or(i32::MAX),
+            ok: error.is_none(),
+            status: status.map(|status| status.as_u16().into()),
+            error: error.as_deref(),
+        };
+        let mut db = self.db.lock().await;
+        let result = diesel::insert_into(webhook_deliveries::table)
+            .values(&delivery)
+            .execute(&mut *db)
+            .and_then(|_| NewWebhookDelivery::prune(&mut db));
+        if let Err(err) = result {
+            eprintln!("Couldn't record webhook delivery: {}", err);
+        }
+    }
+}
+
+/// Who a webhook belongs to, which decides the key its payloads are signed with.
+#[derive(Debug, Clone, Copy)]
+enum Recipient {
+    Ring,
+    Member,
+}
+
+/// The key a member's own webhook payloads are signed with: the hex digest of the
+/// HMAC-SHA256 of their slug, keyed with the ring's secret. `webhook-key <slug>` prints it, to
+/// hand to the member.
+pub fn member_key(secret: &str, slug: &str) -> String {
+    hmac_hex(secret, slug)
+}
+
+fn hmac_hex(key: &str, message: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::QueryDsl;
+    use rocket::tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::models::open_database;
+
+    /// A receiver that answers each request with the next of `statuses`, and hands over the
+    /// headers and body of everything it gets.
+    async fn receiver(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::unbounded_channel();
+        spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0; 4096];
+                let (head, body) = loop {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break (head.to_lowercase(), body.to_string());
+                        }
+                    }
+                };
+                let response = format!(
+                    "HTTP/1.1 {} Whatever\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                sender.send((head, body)).unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn webhooks(secret: Option<&str>) -> Webhooks {
+        let db = open_database(":memory:").unwrap();
+        let mut config = RingConfig::default();
+        config.webhooks.secret = secret.map(str::to_string);
+        config.webhooks.retry_backoff = 10;
+        Webhooks::new(&config, Arc::new(Mutex::new(db)))
+    }
+
+    fn transition() -> Transition {
+        Transition {
+            event: "health",
+            ring: "testring".to_string(),
+            slug: "someone".to_string(),
+            old_state: "ok",
+            new_state: "timeout",
+            reason: "timed out".to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    async fn deliveries(webhooks: &Webhooks) -> Vec<(i32, bool, Option<i32>)> {
+        let mut db = webhooks.db.lock().await;
+        webhook_deliveries::table
+            .select((
+                webhook_deliveries::attempts,
+                webhook_deliveries::ok,
+                webhook_deliveries::status,
+            ))
+            .load(&mut *db)
+            .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn delivers_signed_payload_after_retrying() {
+        let (url, mut received) = receiver(vec![503, 200]).await;
+        let webhooks = webhooks(Some("s3cret"));
+        let transition = transition();
+        let body = serde_json::to_string(&transition).unwrap();
+        webhooks
+            .deliver(&url, &transition, body.clone(), Recipient::Ring)
+            .await;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(body.as_bytes());
+        let signature = format!(
+            "x-ring-signature: sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        );
+        for _ in 0..2 {
+            let (head, received_body) = received.recv().await.unwrap();
+            assert!(head.starts_with("post /hook "));
+            assert!(head.contains("content-type: application/json"));
+            assert!(head.lines().any(|line| line == signature));
+            assert_eq!(received_body, body);
+        }
+        let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["event"], "health");
+        assert_eq!(payload["slug"], "someone");
+        assert_eq!(payload["old_state"], "ok");
+        assert_eq!(payload["new_state"], "timeout");
+        assert_eq!(deliveries(&webhooks).await, vec![(2, true, Some(200))]);
+    }
+
+    #[rocket::async_test]
+    async fn gives_up_on_rejected_payloads() {
+        let (url, mut received) = receiver(vec![400]).await;
+        let webhooks = webhooks(Some("s3cret"));
+        let transition = transition();
+        let body = serde_json::to_string(&transition).unwrap();
+        webhooks
+            .deliver(&url, &transition, body, Recipient::Ring)
+            .await;
+
+        assert!(received.recv().await.is_some());
+        assert_eq!(deliveries(&webhooks).await, vec![(1, false, Some(400))]);
+    }
+
+    #[rocket::async_test]
+    async fn gives_up_after_retries() {
+        let (url, _received) = receiver(vec![500; 4]).await;
+        let webhooks = webhooks(None);
+        let transition = transition();
+        let body = serde_json::to_string(&transition).unwrap();
+        webhooks
+            .deliver(&url, &transition, body, Recipient::Ring)
+            .await;
+
+        assert_eq!(deliveries(&webhooks).await, vec![(4, false, Some(500))]);
+    }
+
+    #[test]
+    fn derives_member_keys_from_the_secret_and_slug() {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(b"someone");
+        let key = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(member_key("s3cret", "someone"), key);
+        assert_ne!(member_key("s3cret", "someone_else"), key);
+        assert_ne!(member_key("other", "someone"), key);
+    }
+
+    #[rocket::async_test]
+    async fn signs_member_webhooks_with_their_own_key() {
+        let (url, mut received) = receiver(vec![200]).await;
+        let webhooks = webhooks(Some("s3cret"));
+        let transition = transition();
+        let body = serde_json::to_string(&transition).unwrap();
+        webhooks
+            .deliver(&url, &transition, body.clone(), Recipient::Member)
+            .await;
+
+        let key = member_key("s3cret", "someone");
+        let signature = format!("x-ring-signature: sha256={}", hmac_hex(&key, &body));
+        let (head, _) = received.recv().await.unwrap();
+        assert!(head.lines().any(|line| line == signature));
+        assert!(!head.contains(&hmac_hex("s3cret", &body)));
+    }
+
+    #[rocket::async_test]
+    async fn leaves_payloads_unsigned_without_a_secret() {
+        let (url, mut received) = receiver(vec![200, 200]).await;
+        let webhooks = webhooks(None);
+        let transition = transition();
+        let body = serde_json::to_string(&transition).unwrap();
+        for recipient in [Recipient::Ring, Recipient::Member] {
+            webhooks
+                .deliver(&url, &transition, body.clone(), recipient)
+                .await;
+            let (head, _) = received.recv().await.unwrap();
+            assert!(!head.contains("x-ring-signature"));
+        }
+    }
+}